@@ -1,32 +1,136 @@
-use std::io::{self, BufRead};
+use std::{
+    io::{self, BufRead, Read},
+    sync::mpsc,
+    thread,
+};
 mod services;
 mod types;
 use crate::{
-    services::commands_processor::CommandsProcessor,
-    types::{Command, Response},
+    services::{
+        commands_processor::{self, CommandsProcessor},
+        output,
+    },
+    types::{Command, ErrorCode, Response, SessionCommand, SessionResponse},
 };
 
+/// Longer command lines are skipped instead of being buffered (16MB)
+const MAX_COMMAND_BYTES: usize = 16 * 1024 * 1024;
+
 fn main() -> io::Result<()> {
-    let stdin = io::stdin();
-    let reader = stdin.lock();
     let mut processor = CommandsProcessor::new();
+    let open_cancel = processor.open_cancel();
+
+    // Commands are read on their own thread so that a long OpenFile can be cancelled
+    // by a command sent while it is still indexing
+    let (sender, receiver) = mpsc::channel::<SessionCommand>();
+    let reader = thread::spawn(move || -> io::Result<()> {
+        let mut stdin = io::stdin().lock();
+        let mut input = Vec::new();
 
-    for line in reader.lines() {
-        let input_str = line?;
-        let command: Command = match serde_json::from_str(&input_str) {
-            Ok(cmd) => cmd,
-            Err(e) => {
-                let response = Response::Info {
-                    message: format!("Invalid JSON command: {}", e),
+        while let Some(complete) = read_command_line(&mut stdin, &mut input)? {
+            if !complete {
+                let response = SessionResponse {
+                    file_id: None,
+                    response: Response::Error {
+                        message: format!(
+                            "command too large: commands are limited to {} bytes",
+                            MAX_COMMAND_BYTES
+                        ),
+                        code: Some(ErrorCode::InvalidArgument),
+                    },
                 };
-                eprintln!("{}", serde_json::to_string(&response).unwrap());
+                output::send(&response);
                 continue;
             }
+
+            // Bare unit commands (e.g. "GetParsingInformation") aren't objects, so they can't carry a file_id
+            let request = serde_json::from_slice::<SessionCommand>(&input).or_else(|e| {
+                serde_json::from_slice::<Command>(&input)
+                    .map(|command| SessionCommand {
+                        file_id: None,
+                        command,
+                    })
+                    .map_err(|_| e)
+            });
+            let request = match request {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    let response = Response::Info {
+                        message: format!("Invalid JSON command: {}", e),
+                    };
+                    eprintln!("{}", serde_json::to_string(&response).unwrap());
+                    continue;
+                }
+            };
+
+            // Opening or closing a file supersedes the file being opened in the same session
+            if matches!(
+                request.command,
+                Command::OpenFile { .. }
+                    | Command::OpenTail { .. }
+                    | Command::OpenWindow { .. }
+                    | Command::CloseFile
+                    | Command::CancelOpen
+            ) && open_cancel.request(commands_processor::session_key(&request.file_id))
+                && matches!(request.command, Command::CancelOpen)
+            {
+                // The cancelled OpenFile answers with "open cancelled"
+                continue;
+            }
+
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    });
+
+    for request in receiver {
+        // Switching formats is answered in the old one, everything after uses the new one
+        if let Command::SetProtocol { format } = request.command {
+            let response = SessionResponse {
+                file_id: request.file_id,
+                response: Response::ProtocolSet { format },
+            };
+            output::switch_format(format, &response);
+            continue;
+        }
+
+        let response = SessionResponse {
+            file_id: request.file_id.clone(),
+            response: processor.process_command(request.file_id, request.command),
         };
+        output::send(&response);
+    }
 
-        let response = processor.process_command(command);
-        println!("{}", serde_json::to_string(&response).unwrap());
+    reader.join().expect("stdin reader panicked")
+}
+
+/// Reads the next line of input into buf, without its line ending
+/// Returns None at the end of input, and false when the line was longer than
+/// MAX_COMMAND_BYTES: the rest of it is skipped without being stored
+fn read_command_line(input: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<Option<bool>> {
+    buf.clear();
+    let read = input
+        .by_ref()
+        .take(MAX_COMMAND_BYTES as u64 + 1)
+        .read_until(b'\n', buf)?;
+    if read == 0 {
+        return Ok(None);
+    }
+
+    if buf.last() != Some(&b'\n') && buf.len() > MAX_COMMAND_BYTES {
+        input.skip_until(b'\n')?;
+        buf.clear();
+        return Ok(Some(false));
     }
 
-    Ok(())
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+    }
+    Ok(Some(true))
 }
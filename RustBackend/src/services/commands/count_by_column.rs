@@ -0,0 +1,87 @@
+use std::{collections::HashMap, time::Instant};
+
+use crate::{
+    services::{FileState, commands::utils},
+    types::{ErrorCode, Response},
+};
+
+const MAX_VALUES: usize = 1_000; // Only the 1000 most frequent values are returned
+
+/// Counts the lines of the whole file by the value of one parsed column
+/// Values are returned from the most to the least frequent, lines that don't
+/// parse are counted apart
+pub fn count_by_column(fs: &FileState, column: u8, file_id: &Option<String>) -> Response {
+    let started_at = Instant::now();
+    let processor = &fs.processor;
+    let column_total = utils::column_count(processor, &fs.line_parser, fs.nbr_columns);
+
+    if column as usize >= column_total {
+        return Response::Error {
+            message: format!(
+                "column ({}) out of bounds (lines have {} columns)",
+                column, column_total
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    let ((counts, unparsed_lines), complete) = utils::scan_chunks(
+        processor,
+        "Counting",
+        file_id,
+        |start_line, lines| count_chunk(fs, column as usize, column_total, start_line, lines),
+        |(mut counts, unparsed), (other_counts, other_unparsed)| {
+            for (value, count) in other_counts {
+                *counts.entry(value).or_insert(0) += count;
+            }
+            (counts, unparsed + other_unparsed)
+        },
+    );
+
+    let distinct_values = counts.len() as u64;
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(MAX_VALUES);
+
+    Response::ColumnCounts {
+        column,
+        counts,
+        distinct_values,
+        unparsed_lines,
+        complete,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
+
+fn count_chunk(
+    fs: &FileState,
+    column: usize,
+    column_total: usize,
+    start_line: u64,
+    lines: &[String],
+) -> (HashMap<String, u64>, u64) {
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let mut unparsed = 0;
+
+    // Without a parser every line is a single column, counting identical lines
+    let parsed = utils::parse_data(
+        &fs.line_parser,
+        fs.nbr_columns,
+        fs.processor.strip_ansi(),
+        lines,
+        start_line,
+        false,
+    );
+    for mut columns in parsed {
+        // Lines that failed to parse only have the raw line in column 0
+        if columns.len() != column_total {
+            unparsed += 1;
+            continue;
+        }
+
+        let value = columns.swap_remove(column);
+        *counts.entry(value).or_insert(0) += 1;
+    }
+
+    (counts, unparsed)
+}
@@ -0,0 +1,58 @@
+use std::io::{BufReader, Read};
+
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::{
+    services::{
+        self,
+        commands::{self, utils::log_format_patterns},
+    },
+    types::{ErrorCode, Response},
+};
+
+/// How much of the file is looked at, enough for a few dozen lines
+const PREVIEW_BYTES: u64 = 8192;
+
+/// Detects the log format from the first few KB of a file, without opening it
+/// (no index is built and no watcher is started)
+pub fn detect_format(path: &str) -> Response {
+    let encoding_label = match commands::get_file_encoding(path, None) {
+        Response::Encoding {
+            encoding,
+            is_supported: true,
+        } => encoding,
+        Response::Encoding { .. } => String::from("utf-8"),
+        error => return error,
+    };
+
+    let file = match services::open_checked(path) {
+        Ok(file) => file,
+        Err(err) => return err.into(),
+    };
+
+    let mut buffer = Vec::with_capacity(PREVIEW_BYTES as usize);
+    if let Err(e) = BufReader::new(file)
+        .take(PREVIEW_BYTES)
+        .read_to_end(&mut buffer)
+    {
+        return Response::Error {
+            message: format!("Failed to read file: {}", e),
+            code: Some(ErrorCode::Io),
+        };
+    }
+
+    let encoding = Encoding::for_label(encoding_label.as_bytes()).unwrap_or(UTF_8);
+    let (text, _encoding, _had_errors) = encoding.decode(&buffer);
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    // The last line is probably cut off when the preview doesn't cover the whole file
+    if buffer.len() as u64 == PREVIEW_BYTES {
+        lines.pop();
+    }
+
+    Response::ParsingInformation {
+        log_format: log_format_patterns::detect_format_from_lines(&lines),
+        sampled: None,
+        matched: None,
+    }
+}
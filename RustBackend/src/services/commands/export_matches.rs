@@ -0,0 +1,99 @@
+use std::io::{BufWriter, Write};
+
+use crate::{
+    services::{self, commands::utils::LineProgress, file_processor::FileProcessor, write_error},
+    types::{CodedError, Response},
+};
+
+/// Lines read from the source file at once, by ReplaceToFile too
+pub(super) const BATCH_SIZE: u64 = 10_000;
+
+/// Writes the given lines, in file order, to out_path
+/// By default the lines are copied byte for byte (original encoding and line endings),
+/// with `utf8` they're decoded and written as UTF-8 with \n line endings
+pub fn export_matches(
+    processor: &FileProcessor,
+    out_path: &str,
+    mut line_numbers: Vec<u64>,
+    utf8: bool,
+    file_id: &Option<String>,
+) -> Response {
+    line_numbers.sort_unstable();
+    line_numbers.dedup();
+
+    let line_count = processor.index.len() as u64;
+    let (valid, invalid_lines): (Vec<u64>, Vec<u64>) = line_numbers
+        .into_iter()
+        .partition(|&line| line < line_count);
+
+    let file = match services::create_checked_output(out_path, &processor.file_path) {
+        Ok(file) => file,
+        Err(err) => return err.into(),
+    };
+    let mut writer = BufWriter::new(file);
+
+    // Group consecutive line numbers into ranges, split into batches
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for line in valid {
+        match ranges.last_mut() {
+            Some((start, end)) if *end + 1 == line && line - *start < BATCH_SIZE => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+
+    let valid_count = ranges.iter().map(|(start, end)| end - start + 1).sum();
+    let progress = LineProgress::start("Exporting", valid_count, file_id);
+
+    let mut lines_written = 0u64;
+    let mut bytes_written = 0u64;
+    for (start_line, end_line) in ranges {
+        let result = if utf8 {
+            write_utf8(processor, &mut writer, start_line, end_line)
+        } else {
+            write_raw(processor, &mut writer, start_line, end_line)
+        };
+
+        match result {
+            Ok(bytes) => bytes_written += bytes,
+            Err(err) => return err.into(),
+        }
+        lines_written += end_line - start_line + 1;
+        progress.advance(end_line - start_line + 1);
+    }
+    progress.finish();
+
+    if let Err(e) = writer.flush() {
+        return write_error(e).into();
+    }
+
+    Response::Exported {
+        lines_written,
+        bytes_written,
+        invalid_lines,
+    }
+}
+
+fn write_raw(
+    processor: &FileProcessor,
+    writer: &mut impl Write,
+    start_line: u64,
+    end_line: u64,
+) -> Result<u64, CodedError> {
+    let (bytes, _, _) = processor.read_raw_range(start_line, end_line, u64::MAX)?;
+    writer.write_all(&bytes).map_err(write_error)?;
+    Ok(bytes.len() as u64)
+}
+
+fn write_utf8(
+    processor: &FileProcessor,
+    writer: &mut impl Write,
+    start_line: u64,
+    end_line: u64,
+) -> Result<u64, CodedError> {
+    let mut bytes_written = 0u64;
+    for line in processor.read_lines_range_unclipped(start_line, end_line)? {
+        writeln!(writer, "{}", line).map_err(write_error)?;
+        bytes_written += line.len() as u64 + 1;
+    }
+    Ok(bytes_written)
+}
@@ -0,0 +1,130 @@
+use rayon::prelude::*;
+use regex::Regex;
+use std::time::Instant;
+
+use crate::{
+    services::{
+        FileProcessor,
+        commands::utils::{self, log_format_patterns},
+    },
+    types::{CodedError, ErrorCode, ExtractedRow, Response},
+};
+
+const MAX_ROWS: usize = 1_000; // Stop after extracting from 1000 lines
+
+/// Runs pattern over every line and returns the groups it captured, one row per matching line
+/// Lines are matched whole, whatever the file's format, and only their first match is kept
+/// Chunks are scanned in parallel, batch by batch, and the scan stops at the first MAX_ROWS
+pub fn extract(
+    processor: &FileProcessor,
+    pattern: &str,
+    chunk_size: usize,
+    file_id: &Option<String>,
+) -> Response {
+    let started_at = Instant::now();
+
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(e) => {
+            return Response::Error {
+                message: format!("Invalid regex pattern: {}", e),
+                code: Some(ErrorCode::InvalidRegex),
+            };
+        }
+    };
+    if regex.captures_len() == 1 {
+        return Response::Error {
+            message: String::from(
+                "The pattern has no capture group, use Search or Filter to find lines",
+            ),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    let line_count = processor.index.len();
+    let total_chunks = line_count.div_ceil(chunk_size);
+    let progress = utils::LineProgress::start("Extracting", line_count as u64, file_id);
+
+    // One chunk per thread, so a scan that stops early doesn't read much past its rows
+    let chunks_per_batch = rayon::current_num_threads();
+    let mut rows: Vec<ExtractedRow> = Vec::new();
+    let mut lines_scanned = 0;
+    let mut dropped_rows = false;
+    for batch_start in (0..total_chunks).step_by(chunks_per_batch) {
+        let batch_end = (batch_start + chunks_per_batch).min(total_chunks);
+
+        // Chunks come back in file order, so the first rows of the file are kept
+        let batch: Vec<Vec<ExtractedRow>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|chunk| {
+                let chunk_start = chunk * chunk_size;
+                let count = chunk_size.min(line_count - chunk_start);
+
+                let result =
+                    extract_chunk(processor, &regex, chunk_start, count).unwrap_or_else(|e| {
+                        // Log error but continue with the other chunks
+                        let response = Response::Info {
+                            message: format!(
+                                "Failed to extract chunk starting at line {}: {}",
+                                chunk_start, e.message
+                            ),
+                        };
+                        eprintln!("{}", serde_json::to_string(&response).unwrap());
+
+                        Vec::new()
+                    });
+
+                progress.advance(count as u64);
+                result
+            })
+            .collect();
+
+        lines_scanned += (batch_end * chunk_size).min(line_count) - batch_start * chunk_size;
+        let found: Vec<ExtractedRow> = batch.into_iter().flatten().collect();
+        let room = MAX_ROWS - rows.len();
+        dropped_rows |= found.len() > room;
+        rows.extend(found.into_iter().take(room));
+
+        if rows.len() >= MAX_ROWS {
+            break;
+        }
+    }
+
+    progress.finish();
+
+    Response::Extracted {
+        columns: log_format_patterns::resolve_column_names(None, Some(&regex)),
+        // A file with exactly MAX_ROWS matching lines is still complete
+        complete: !dropped_rows && lines_scanned == line_count,
+        rows,
+        lines_scanned: lines_scanned as u64,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
+
+fn extract_chunk(
+    processor: &FileProcessor,
+    regex: &Regex,
+    start_line: usize,
+    count: usize,
+) -> Result<Vec<ExtractedRow>, CodedError> {
+    let lines = processor.scan_lines_range(start_line as u64, (start_line + count - 1) as u64)?;
+
+    let rows = (start_line as u64..)
+        .zip(&lines)
+        .filter_map(|(line_number, line)| {
+            let caps = regex.captures(line)?;
+            Some(ExtractedRow {
+                line_number,
+                // Groups that didn't take part in the match stay null, keeping columns aligned
+                values: caps
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map(|m| m.as_str().to_string()))
+                    .collect(),
+            })
+        })
+        .collect();
+
+    Ok(rows)
+}
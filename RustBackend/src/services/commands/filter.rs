@@ -0,0 +1,50 @@
+use crate::{
+    services::{FileState, commands},
+    types::{ErrorCode, Response},
+};
+
+/// Keeps the lines where the pattern matches anywhere in the original line, like grep
+/// With `distinct`, only the first line of each distinct line (or value of distinct_column,
+/// which implies distinct) is kept
+pub fn filter(
+    fs: &mut FileState,
+    pattern: &str,
+    chunk_size: usize,
+    distinct: bool,
+    distinct_column: Option<u8>,
+    file_id: &Option<String>,
+) -> Response {
+    let matcher =
+        match commands::build_search_matcher(pattern, false, false, false, false, None, None) {
+            Ok(matcher) => matcher,
+            Err(message) => {
+                return Response::Error {
+                    message,
+                    code: Some(ErrorCode::InvalidRegex),
+                };
+            }
+        };
+
+    if let Some(column) = distinct_column {
+        let column_total =
+            commands::utils::column_count(&fs.processor, &fs.line_parser, fs.nbr_columns);
+        if column as usize >= column_total {
+            return Response::Error {
+                message: format!(
+                    "distinct_column ({}) out of bounds (lines have {} columns)",
+                    column, column_total
+                ),
+                code: Some(ErrorCode::OutOfBounds),
+            };
+        }
+    }
+
+    if distinct || distinct_column.is_some() {
+        let column = distinct_column.map(usize::from);
+        return commands::utils::filter_distinct_lines(fs, chunk_size, column, file_id, |line| {
+            matcher.is_match(line)
+        });
+    }
+
+    commands::utils::filter_lines(fs, chunk_size, file_id, |line| matcher.is_match(line))
+}
@@ -0,0 +1,44 @@
+use crate::{
+    services::{
+        FileState,
+        commands::utils::{self, timestamp},
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Keeps the lines whose timestamp falls between start and end (inclusive)
+/// The timestamp layout comes from the format given to ParseFile, or is detected
+/// from the first non-blank line when no format was set
+pub fn filter_time_range(
+    fs: &mut FileState,
+    start: &str,
+    end: &str,
+    keep_unparsed: bool,
+    file_id: &Option<String>,
+) -> Response {
+    let Some(start_bound) = timestamp::parse_bound(start) else {
+        return invalid_bound(start);
+    };
+    let Some(end_bound) = timestamp::parse_bound(end) else {
+        return invalid_bound(end);
+    };
+
+    let log_format = timestamp::timestamp_format(fs);
+
+    utils::filter_lines(fs, utils::DEFAULT_CHUNK_SIZE, file_id, |line| {
+        match timestamp::parse_timestamp(&log_format, line) {
+            Some(ts) => ts.is_within(&start_bound, &end_bound),
+            None => keep_unparsed,
+        }
+    })
+}
+
+fn invalid_bound(input: &str) -> Response {
+    Response::Error {
+        message: format!(
+            "Invalid time bound \"{}\": expected YYYY-MM-DD HH:MM[:SS] or HH:MM[:SS]",
+            input
+        ),
+        code: Some(ErrorCode::InvalidArgument),
+    }
+}
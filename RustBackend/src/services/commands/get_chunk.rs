@@ -1,26 +1,212 @@
 use crate::{
-    services::{commands::utils, file_processor::FileProcessor},
-    types::Response,
+    services::{
+        commands::utils::{self, LineParser},
+        file_processor::{CLIPPED_MARKER, FileProcessor},
+    },
+    types::{ErrorCode, Response},
 };
 
+/// How the lines of a chunk are sent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChunkOutput {
+    /// Keep optional columns that didn't match as null, answers with a NullableChunk
+    pub explicit_nulls: bool,
+    /// Data goes from the last line down to start_line (newest first),
+    /// everything else describes the lines the same way
+    pub reverse: bool,
+    /// Send the unparsed lines along with the parsed ones, in the same order
+    pub include_raw: bool,
+    /// Cut column values longer than this many bytes, listing them in truncated_columns
+    pub max_column_bytes: Option<usize>,
+    /// Send the lines as written, strip_ansi doesn't apply (GetRawChunk)
+    pub as_written: bool,
+}
+
 pub fn get_chunk(
     processor: &FileProcessor,
     start_line: u64,
     end_line: u64,
-    regex_pattern: &Option<regex::Regex>,
+    parser: &Option<LineParser>,
     nbr_columns: Option<u8>,
+    output: ChunkOutput,
 ) -> Response {
-    let lines = match processor.read_lines_range(start_line, end_line) {
-        Ok(lines) => lines,
-        Err(err) => return Response::Error { message: err },
+    let ChunkOutput {
+        explicit_nulls,
+        reverse,
+        include_raw,
+        max_column_bytes,
+        as_written,
+    } = output;
+
+    if max_column_bytes == Some(0) {
+        return Response::Error {
+            message: String::from("max_column_bytes must be at least 1"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    let line_count = processor.index.len() as u64;
+
+    // Right after the last line is a valid place to read from, there's just nothing there yet
+    // (a client reading at the end of the file while LinesAdded events are on their way)
+    let (lines, clipped_lines, had_decode_errors) = if start_line == line_count {
+        (Vec::new(), Vec::new(), false)
+    } else {
+        match processor.read_lines_range_clipped(start_line, end_line) {
+            Ok(result) => result,
+            Err(err) => return err.into(),
+        }
+    };
+
+    // Bytes of the lines that were read, clipped lines are covered entirely
+    let start_byte = processor.line_start_byte(start_line);
+    let end_byte = if lines.is_empty() {
+        start_byte
+    } else {
+        processor.line_end_byte(end_line.min(line_count - 1))
     };
 
+    let end_line = start_line + lines.len() as u64;
+
+    let raw = include_raw.then(|| {
+        let mut raw = lines.clone();
+        if reverse {
+            raw.reverse();
+        }
+        raw
+    });
+
+    if explicit_nulls {
+        let mut data = utils::parse_data_nullable(
+            parser,
+            nbr_columns,
+            processor.strip_ansi() && !as_written,
+            &lines,
+            start_line,
+            true,
+        );
+        let truncated_columns =
+            truncate_columns(&mut data, start_line, max_column_bytes, |v| v.as_mut());
+        if reverse {
+            data.reverse();
+        }
+        return Response::NullableChunk {
+            data,
+            start_line,
+            end_line,
+            clipped_lines,
+            start_byte,
+            end_byte,
+            had_decode_errors,
+            raw,
+            truncated_columns,
+        };
+    }
+
     // Parse the lines using the regex pattern
-    let data = utils::parse_data(regex_pattern, nbr_columns, &lines, start_line, true);
+    let mut data = utils::parse_data(
+        parser,
+        nbr_columns,
+        processor.strip_ansi() && !as_written,
+        &lines,
+        start_line,
+        true,
+    );
+    let truncated_columns =
+        truncate_columns(&mut data, start_line, max_column_bytes, |text| Some(text));
+    if reverse {
+        data.reverse();
+    }
 
     Response::Chunk {
         data,
         start_line,
-        end_line: start_line + lines.len() as u64,
+        end_line,
+        clipped_lines,
+        start_byte,
+        end_byte,
+        had_decode_errors,
+        focal_line: None,
+        raw,
+        truncated_columns,
+    }
+}
+
+/// Cuts the values longer than max_bytes, returns the line and the column of each one
+/// `value` gives the text of a cell, None for a missing optional column
+fn truncate_columns<T>(
+    data: &mut [Vec<T>],
+    start_line: u64,
+    max_bytes: Option<usize>,
+    value: fn(&mut T) -> Option<&mut String>,
+) -> Vec<(u64, u8)> {
+    let Some(max_bytes) = max_bytes else {
+        return Vec::new();
+    };
+
+    let mut truncated = Vec::new();
+    for (line, columns) in (start_line..).zip(data.iter_mut()) {
+        for (column, cell) in columns.iter_mut().enumerate() {
+            if let Some(text) = value(cell)
+                && text.len() > max_bytes
+            {
+                // Cut before a character that doesn't fit whole
+                let cut = (0..=max_bytes)
+                    .rev()
+                    .find(|&i| text.is_char_boundary(i))
+                    .unwrap_or(0);
+                text.truncate(cut);
+                text.push(CLIPPED_MARKER);
+                truncated.push((line, column as u8));
+            }
+        }
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_files::TempFile;
+
+    fn chunk_at(processor: &FileProcessor, start_line: u64) -> Response {
+        get_chunk(
+            processor,
+            start_line,
+            start_line + 10,
+            &None,
+            None,
+            ChunkOutput::default(),
+        )
+    }
+
+    #[test]
+    fn chunk_at_line_count_is_empty() {
+        let file = TempFile::new(b"a\nb\n");
+        let processor = file.open("utf-8");
+
+        match chunk_at(&processor, 2) {
+            Response::Chunk {
+                data,
+                start_line,
+                end_line,
+                ..
+            } => {
+                assert!(data.is_empty());
+                assert_eq!((start_line, end_line), (2, 2));
+            }
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn chunk_past_line_count_is_out_of_bounds() {
+        let file = TempFile::new(b"a\nb\n");
+        let processor = file.open("utf-8");
+
+        match chunk_at(&processor, 3) {
+            Response::Error { code, .. } => assert_eq!(code, Some(ErrorCode::OutOfBounds)),
+            response => panic!("unexpected response: {:?}", response),
+        }
     }
 }
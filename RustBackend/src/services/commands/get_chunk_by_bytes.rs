@@ -0,0 +1,56 @@
+use crate::{
+    services::{
+        commands::{self, ChunkOutput, utils::LineParser},
+        file_processor::FileProcessor,
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Returns the whole lines covering the byte range [start_byte, end_byte] (inclusive)
+/// A range starting mid-line snaps back to the start of that line, and a range ending
+/// mid-line snaps forward to include the rest of it (newline bytes included)
+pub fn get_chunk_by_bytes(
+    processor: &FileProcessor,
+    start_byte: u64,
+    end_byte: u64,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+) -> Response {
+    if start_byte > end_byte {
+        return Response::Error {
+            message: format!(
+                "Invalid range: start_byte ({}) > end_byte ({})",
+                start_byte, end_byte
+            ),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    let Some(start_line) = processor.line_at_byte(start_byte) else {
+        return Response::Error {
+            message: format!(
+                "start_byte ({}) out of bounds (file has {} indexed bytes)",
+                start_byte,
+                match processor.index.len() as u64 {
+                    0 => 0,
+                    line_count => processor.line_end_byte(line_count - 1),
+                }
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    };
+
+    // Clamp to the last line when the range runs past the end of the index
+    let end_line = processor
+        .line_at_byte(end_byte)
+        .unwrap_or(processor.index.len() as u64 - 1);
+
+    commands::get_chunk(
+        processor,
+        start_line,
+        end_line,
+        parser,
+        nbr_columns,
+        ChunkOutput::default(),
+    )
+}
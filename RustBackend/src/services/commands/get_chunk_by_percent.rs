@@ -0,0 +1,58 @@
+use crate::{
+    services::{
+        commands::{self, ChunkOutput, utils::LineParser},
+        file_processor::FileProcessor,
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Returns `window` lines centered on the line at `percent` of the file
+/// Maps a scrollbar position to lines using the current line count, which the
+/// client may not have yet while the file is growing
+/// The window is shifted, not shrunk, near the start and the end of the file
+pub fn get_chunk_by_percent(
+    processor: &FileProcessor,
+    percent: f32,
+    window: u64,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+) -> Response {
+    if !percent.is_finite() {
+        return Response::Error {
+            message: format!("Invalid percent: {}", percent),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    if window == 0 {
+        return Response::Error {
+            message: String::from("window must be at least 1 line"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    let line_count = processor.index.len() as u64;
+    if line_count == 0 {
+        return Response::Error {
+            message: String::from("File is empty"),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    let percent = percent.clamp(0.0, 100.0) as f64;
+    let center_line = ((percent / 100.0 * line_count as f64) as u64).min(line_count - 1);
+
+    let window = window.min(line_count);
+    let start_line = center_line
+        .saturating_sub(window / 2)
+        .min(line_count - window);
+
+    commands::get_chunk(
+        processor,
+        start_line,
+        start_line + window - 1,
+        parser,
+        nbr_columns,
+        ChunkOutput::default(),
+    )
+}
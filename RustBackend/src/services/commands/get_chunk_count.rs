@@ -0,0 +1,35 @@
+use crate::{
+    services::{
+        commands::{self, ChunkOutput, utils::LineParser},
+        file_processor::FileProcessor,
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Returns up to `count` lines from start_line, fewer when the file ends before
+/// The chunk's end_line is where the next page starts
+pub fn get_chunk_count(
+    processor: &FileProcessor,
+    start_line: u64,
+    count: u64,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+    output: ChunkOutput,
+) -> Response {
+    if count == 0 {
+        return Response::Error {
+            message: String::from("count must be at least 1 line"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    // get_chunk stops at the last line on its own
+    commands::get_chunk(
+        processor,
+        start_line,
+        start_line.saturating_add(count - 1),
+        parser,
+        nbr_columns,
+        output,
+    )
+}
@@ -0,0 +1,31 @@
+use crate::{
+    services::{
+        FileState,
+        commands::utils::{self, LineParser, log_format_patterns},
+    },
+    types::LogFormat,
+};
+
+/// Column names of the active parsing settings, in the same order as the parsed columns
+pub fn column_names(fs: &FileState) -> Vec<String> {
+    match &fs.line_parser {
+        // Delimited columns have no names, they're numbered like unnamed capture groups
+        Some(LineParser::Delimited(_)) => {
+            (0..utils::column_count(&fs.processor, &fs.line_parser, fs.nbr_columns))
+                .map(|i| format!("column_{}", i))
+                .collect()
+        }
+        Some(LineParser::W3CFields(names)) => names.clone(),
+        Some(LineParser::DockerJson) => {
+            log_format_patterns::get_column_names(&LogFormat::DockerJson)
+                .unwrap_or_default()
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        }
+        Some(LineParser::Regex { regex, .. }) => {
+            log_format_patterns::resolve_column_names(fs.log_format.as_ref(), Some(regex))
+        }
+        None => log_format_patterns::resolve_column_names(fs.log_format.as_ref(), None),
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{
+    services::{
+        commands::{self, ChunkOutput, utils::LineParser},
+        file_processor::FileProcessor,
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Returns the lines from `before` lines above `line` to `after` lines below it,
+/// with `line` marked as the focal line
+/// The range is clamped to the file, so a line near the start or the end gets fewer lines
+pub fn get_context(
+    processor: &FileProcessor,
+    line: u64,
+    before: u64,
+    after: u64,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+) -> Response {
+    let line_count = processor.index.len() as u64;
+    if line >= line_count {
+        return Response::Error {
+            message: format!(
+                "line ({}) out of bounds (file has {} lines)",
+                line, line_count
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    let start_line = line.saturating_sub(before);
+    let end_line = line.saturating_add(after).min(line_count - 1);
+
+    match commands::get_chunk(
+        processor,
+        start_line,
+        end_line,
+        parser,
+        nbr_columns,
+        ChunkOutput::default(),
+    ) {
+        Response::Chunk {
+            data,
+            start_line,
+            end_line,
+            clipped_lines,
+            start_byte,
+            end_byte,
+            had_decode_errors,
+            raw,
+            truncated_columns,
+            ..
+        } => Response::Chunk {
+            data,
+            start_line,
+            end_line,
+            clipped_lines,
+            start_byte,
+            end_byte,
+            had_decode_errors,
+            focal_line: Some(line),
+            raw,
+            truncated_columns,
+        },
+        other => other,
+    }
+}
@@ -1,18 +1,26 @@
-use std::io::Read;
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
 
 use encoding_rs::Encoding;
+use memchr::{memchr, memrchr};
 
-use crate::Response;
+use crate::{
+    Response, services,
+    types::{CodedError, ErrorCode},
+};
 
-pub fn get_file_encoding(path: &str) -> Response {
-    let result = get_file_encoding_helper(path);
+/// Bytes sampled for detection when the client doesn't say (64KB)
+const DEFAULT_DETECT_BYTES: usize = 64 * 1024;
+
+/// Detects the encoding of a file from a sample of detect_bytes bytes
+/// (DEFAULT_DETECT_BYTES when None)
+pub fn get_file_encoding(path: &str, detect_bytes: Option<usize>) -> Response {
+    let result = get_file_encoding_helper(path, detect_bytes.unwrap_or(DEFAULT_DETECT_BYTES));
     let encoding_name = match result {
         Ok(name) => name,
-        Err(err) => {
-            eprintln!("{}", serde_json::to_string(&err).unwrap());
-            std::process::exit(1);
-        }
+        Err(err) => return err.into(),
     };
 
     let is_supported = ["UTF-16BE", "UTF-16LE", "UTF-16"]
@@ -37,36 +45,62 @@ pub fn get_file_encoding(path: &str) -> Response {
     }
 }
 
-fn get_file_encoding_helper(path: &str) -> Result<String, Response> {
-    let path = Path::new(path);
-    if !path.is_absolute() {
-        let response = Response::Error {
-            message: "Path must be absolute".to_string(),
-        };
-        return Err(response);
-    }
+fn get_file_encoding_helper(path: &str, detect_bytes: usize) -> Result<String, CodedError> {
+    let mut file = services::open_checked(path)?;
 
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            let response = Response::Error {
-                message: format!("Couldn't open the file: {}", e),
-            };
-            return Err(response);
+    let buffer = read_sample(&mut file, detect_bytes)
+        .map_err(|e| CodedError::new(ErrorCode::Io, format!("Failed to read file: {}", e)))?;
+
+    // Use chardet for reliable encoding detection
+    let result = chardet::detect(&buffer);
+    Ok(result.0)
+}
+
+/// Reads the head of the file, plus a few lines from its middle and its end when
+/// the file is large: non-ASCII content often appears only after a long ASCII header
+fn read_sample(file: &mut File, detect_bytes: usize) -> std::io::Result<Vec<u8>> {
+    let file_size = file.metadata()?.len();
+
+    let mut sample = read_at(file, 0, detect_bytes)?;
+
+    let extra_bytes = detect_bytes / 4;
+    if file_size > 4 * detect_bytes as u64 {
+        for offset in [file_size / 2, file_size - extra_bytes as u64] {
+            let bytes = read_at(file, offset, extra_bytes)?;
+            sample.extend_from_slice(whole_lines(&bytes));
         }
+    }
+
+    Ok(sample)
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut buffer = Vec::with_capacity(len);
+    file.take(len as u64).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Trims bytes read from the middle of a file to whole lines, so that no character
+/// is cut in half (a UTF-16LE newline is 0A 00, the 00 is kept with its line)
+fn whole_lines(bytes: &[u8]) -> &[u8] {
+    let (Some(first), Some(last)) = (memchr(b'\n', bytes), memrchr(b'\n', bytes)) else {
+        return &[];
     };
 
-    let reader = BufReader::new(file);
+    let mut start = first + 1;
+    if bytes.get(start) == Some(&0) {
+        start += 1;
+    }
 
-    let mut buffer = Vec::with_capacity(8192);
-    reader
-        .take(8192)
-        .read_to_end(&mut buffer)
-        .map_err(|e| Response::Error {
-            message: format!("Failed to read file: {}", e),
-        })?;
+    let mut end = last + 1;
+    if bytes.get(end) == Some(&0) {
+        end += 1;
+    }
 
-    // Use chardet for reliable encoding detection
-    let result = chardet::detect(&buffer);
-    Ok(result.0)
+    if start >= end {
+        return &[];
+    }
+    &bytes[start..end]
 }
@@ -0,0 +1,25 @@
+use std::time::UNIX_EPOCH;
+
+use crate::{services, types::Response};
+
+/// Size and modification time of a file, straight from the filesystem
+/// The file isn't opened or indexed, so it's cheap enough for a file browser
+pub fn get_file_info(path: &str) -> Response {
+    let metadata = match services::metadata_checked(path) {
+        Ok(metadata) => metadata,
+        Err(err) => return err.into(),
+    };
+
+    // Some filesystems don't record it
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_millis() as i64);
+
+    Response::FileInfo {
+        size_bytes: metadata.len(),
+        modified,
+        is_file: metadata.is_file(),
+    }
+}
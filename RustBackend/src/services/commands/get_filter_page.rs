@@ -0,0 +1,83 @@
+use crate::{
+    services::{FileState, commands::utils},
+    types::{ErrorCode, FilteredLine, Response},
+};
+
+/// Pages can't be larger than this, to keep responses small
+const MAX_PAGE_LINES: u64 = 10_000;
+
+/// Reads and parses `count` lines of the last filter's results, starting at the `offset`th one
+/// Consecutive lines are read together, like GetLines
+pub fn get_filter_page(fs: &FileState, offset: u64, count: u64) -> Response {
+    let Some(filter) = &fs.filter else {
+        return Response::Error {
+            message: String::from("No filter results, run a filter first"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    };
+
+    let total_matches = filter.lines.len() as u64;
+    if offset > total_matches {
+        return Response::Error {
+            message: format!(
+                "offset ({}) out of bounds (the filter kept {} lines)",
+                offset, total_matches
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    let end = offset
+        .saturating_add(count.min(MAX_PAGE_LINES))
+        .min(total_matches);
+    let page = &filter.lines[offset as usize..end as usize];
+
+    // Group consecutive line numbers into ranges
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for &line in page {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+
+    let mut occurrences = filter
+        .occurrences
+        .as_ref()
+        .map(|occurrences| occurrences[offset as usize..end as usize].iter().copied());
+
+    let mut lines = Vec::with_capacity(page.len());
+    for (start_line, end_line) in ranges {
+        let raw_lines = match fs.processor.read_lines_range(start_line, end_line) {
+            Ok(raw_lines) => raw_lines,
+            Err(err) => return err.into(),
+        };
+
+        let parsed = utils::parse_data(
+            &fs.line_parser,
+            fs.nbr_columns,
+            fs.processor.strip_ansi(),
+            &raw_lines,
+            start_line,
+            false,
+        );
+        lines.extend(
+            parsed
+                .into_iter()
+                .zip(start_line..)
+                .map(|(columns, line_number)| FilteredLine {
+                    line_number,
+                    columns,
+                    occurrences: occurrences.as_mut().and_then(Iterator::next),
+                }),
+        );
+    }
+
+    Response::FilterResults {
+        total_lines: lines.len() as u64,
+        lines,
+        filter_complete: filter.complete,
+        total_matches,
+        offset,
+    }
+}
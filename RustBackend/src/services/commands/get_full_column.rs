@@ -0,0 +1,68 @@
+use crate::{
+    services::{
+        commands::utils::{self, LineParser},
+        file_processor::FileProcessor,
+    },
+    types::{ErrorCode, Response},
+};
+
+/// The whole value of one column of a line, for a cell cut by GetChunk's max_column_bytes
+/// The line is read and parsed again, with `explicit_nulls` counting columns like a
+/// NullableChunk does
+pub fn get_full_column(
+    processor: &FileProcessor,
+    line: u64,
+    column: u8,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+    explicit_nulls: bool,
+) -> Response {
+    let text = match processor.read_line(line) {
+        Ok(text) => text,
+        Err(err) => return err.into(),
+    };
+    let lines = [text];
+
+    let mut columns: Vec<Option<String>> = if explicit_nulls {
+        utils::parse_data_nullable(
+            parser,
+            nbr_columns,
+            processor.strip_ansi(),
+            &lines,
+            line,
+            false,
+        )
+    } else {
+        utils::parse_data(
+            parser,
+            nbr_columns,
+            processor.strip_ansi(),
+            &lines,
+            line,
+            false,
+        )
+        .into_iter()
+        .map(|columns| columns.into_iter().map(Some).collect())
+        .collect()
+    }
+    .pop()
+    .unwrap_or_default();
+
+    if column as usize >= columns.len() {
+        return Response::Error {
+            message: format!(
+                "column ({}) out of bounds (line {} has {} columns)",
+                column,
+                line,
+                columns.len()
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    Response::FullColumn {
+        line,
+        column,
+        value: columns.swap_remove(column as usize),
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{
+    services::file_processor::FileProcessor,
+    types::{ErrorCode, Response},
+};
+
+/// Returns the length of each line from start_line to end_line (inclusive)
+/// Lengths are in bytes, excluding the newline, and come straight from the index
+/// without reading the file. For multibyte encodings (UTF-8, UTF-16) this is an
+/// upper bound of the character count, which is enough to estimate row heights.
+pub fn get_line_lengths(processor: &FileProcessor, start_line: u64, end_line: u64) -> Response {
+    let line_count = processor.index.len() as u64;
+
+    if start_line >= line_count {
+        return Response::Error {
+            message: format!(
+                "start_line ({}) out of bounds (file has {} lines)",
+                start_line, line_count
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    }
+
+    if start_line > end_line {
+        return Response::Error {
+            message: format!(
+                "Invalid range: start_line ({}) > end_line ({})",
+                start_line, end_line
+            ),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    // Clamp end_line to available lines
+    let actual_end_line = end_line.min(line_count - 1);
+
+    let lengths = (start_line..=actual_end_line)
+        .map(|line| processor.line_byte_len(line))
+        .collect();
+
+    Response::LineLengths {
+        lengths,
+        start_line,
+        end_line: actual_end_line + 1,
+    }
+}
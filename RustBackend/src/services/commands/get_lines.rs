@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    services::{
+        commands::utils::{self, LineParser},
+        file_processor::FileProcessor,
+    },
+    types::Response,
+};
+
+/// Returns specific, possibly non-contiguous lines (bookmarks, jump lists...)
+/// Requested lines are sorted and deduplicated, and consecutive ones are read together
+pub fn get_lines(
+    processor: &FileProcessor,
+    mut requested: Vec<u64>,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+) -> Response {
+    requested.sort_unstable();
+    requested.dedup();
+
+    // Out of range lines are reported instead of failing the whole request
+    let line_count = processor.index.len() as u64;
+    let (valid, invalid_lines): (Vec<u64>, Vec<u64>) =
+        requested.into_iter().partition(|&line| line < line_count);
+
+    let mut lines = BTreeMap::new();
+
+    // Group consecutive line numbers into ranges
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for line in valid {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+
+    for (start_line, end_line) in ranges {
+        let raw_lines = match processor.read_lines_range(start_line, end_line) {
+            Ok(raw_lines) => raw_lines,
+            Err(err) => return err.into(),
+        };
+
+        let parsed = utils::parse_data(
+            parser,
+            nbr_columns,
+            processor.strip_ansi(),
+            &raw_lines,
+            start_line,
+            false,
+        );
+        for (offset, columns) in parsed.into_iter().enumerate() {
+            lines.insert(start_line + offset as u64, columns);
+        }
+    }
+
+    Response::Lines {
+        lines,
+        invalid_lines,
+    }
+}
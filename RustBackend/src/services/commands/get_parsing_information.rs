@@ -1,27 +1,48 @@
 use crate::{
     services::{commands::utils::log_format_patterns, file_processor::FileProcessor},
-    types::Response,
+    types::{LogFormat, Response},
 };
 
 pub fn get_parsing_information(processor: &FileProcessor) -> Response {
-    // Read the first line
-    let first_line = match processor.read_lines_range(0, 1) {
-        Ok(lines) => {
-            if lines.is_empty() {
-                return Response::ParsingInformation {
-                    log_format: log_format_patterns::detect_format(""),
-                };
-            }
-            lines[0].clone()
-        }
-        Err(_) => {
-            return Response::ParsingInformation {
-                log_format: log_format_patterns::detect_format(""),
-            };
+    // Detect on the first non-blank line near the top of the file
+    let log_format = processor
+        .read_lines_range(0, log_format_patterns::LEADING_LINES - 1)
+        .map_or(LogFormat::Other, |lines| {
+            log_format_patterns::detect_format_from_first_entry(&lines)
+        });
+
+    Response::ParsingInformation {
+        log_format,
+        sampled: None,
+        matched: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_files::TempFile;
+
+    const CLF_LINE: &str =
+        r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326"#;
+
+    fn detected_format(contents: &str) -> LogFormat {
+        let file = TempFile::new(contents.as_bytes());
+        match get_parsing_information(&file.open("utf-8")) {
+            Response::ParsingInformation { log_format, .. } => log_format,
+            response => panic!("unexpected response: {:?}", response),
         }
-    };
+    }
 
-    let log_format = log_format_patterns::detect_format(&first_line);
+    #[test]
+    fn detect_after_leading_blank_line() {
+        let contents = format!("\n{}\n{}\n", CLF_LINE, CLF_LINE);
+        assert_eq!(detected_format(&contents), LogFormat::CommonLogFormat);
+    }
 
-    Response::ParsingInformation { log_format }
+    #[test]
+    fn detect_indented_first_entry() {
+        let contents = format!("  {}\n{}\n", CLF_LINE, CLF_LINE);
+        assert_eq!(detected_format(&contents), LogFormat::CommonLogFormat);
+    }
 }
@@ -0,0 +1,28 @@
+use crate::{
+    services::{commands::utils, file_processor::FileProcessor},
+    types::Response,
+};
+
+/// Larger ranges are cut to this many bytes (1MB)
+const MAX_RAW_BYTES: u64 = 1024 * 1024;
+
+/// Returns the undecoded bytes of lines start_line to end_line (inclusive), base64 encoded
+/// Nothing is stripped (BOM, \r, NULs...), which helps to understand lines that look wrong
+pub fn get_raw_bytes(processor: &FileProcessor, start_line: u64, end_line: u64) -> Response {
+    let (bytes, start_byte, truncated) =
+        match processor.read_raw_range(start_line, end_line, MAX_RAW_BYTES) {
+            Ok(result) => result,
+            Err(err) => return err.into(),
+        };
+
+    let line_count = processor.index.len() as u64;
+
+    Response::RawBytes {
+        data: utils::base64::encode(&bytes),
+        start_line,
+        end_line: end_line.min(line_count - 1) + 1,
+        start_byte,
+        byte_count: bytes.len() as u64,
+        truncated,
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{
+    services::{
+        commands::{ChunkOutput, get_chunk},
+        file_processor::FileProcessor,
+    },
+    types::Response,
+};
+
+/// The lines as they are in the file, one string each, even when a format is set
+/// The session's parser is left as it is, to compare raw lines with how they're parsed
+pub fn get_raw_chunk(processor: &FileProcessor, start_line: u64, end_line: u64) -> Response {
+    get_chunk(
+        processor,
+        start_line,
+        end_line,
+        &None,
+        None,
+        ChunkOutput {
+            as_written: true,
+            ..Default::default()
+        },
+    )
+}
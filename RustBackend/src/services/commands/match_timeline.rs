@@ -0,0 +1,48 @@
+use std::time::Instant;
+
+use crate::{
+    services::{
+        FileState,
+        commands::{self, time_histogram, utils::timestamp},
+    },
+    types::{ErrorCode, Response},
+};
+
+/// Counts the lines matching `pattern` in `buckets` equal time intervals between the
+/// oldest and the newest matching timestamp, matches without a timestamp are counted apart
+pub fn match_timeline(
+    fs: &FileState,
+    pattern: &str,
+    buckets: u32,
+    file_id: &Option<String>,
+) -> Response {
+    let started_at = Instant::now();
+
+    if let Err(err) = time_histogram::check_buckets(buckets) {
+        return err.into();
+    }
+
+    let matcher =
+        match commands::build_search_matcher(pattern, false, false, false, false, None, None) {
+            Ok(matcher) => matcher,
+            Err(message) => {
+                return Response::Error {
+                    message,
+                    code: Some(ErrorCode::InvalidRegex),
+                };
+            }
+        };
+
+    let log_format = timestamp::timestamp_format(fs);
+    let ((per_second, untimed), complete) =
+        time_histogram::count_per_second(fs, &log_format, Some(&matcher), "Searching", file_id);
+    let (counts, boundaries) = time_histogram::bin(&per_second, buckets as u64, &log_format);
+
+    Response::MatchTimeline {
+        counts,
+        boundaries,
+        untimed,
+        complete,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
@@ -1,13 +1,72 @@
+mod count_by_column;
+mod detect_format;
+mod export_matches;
+mod extract;
+mod filter;
+mod filter_time_range;
 mod get_chunk;
+mod get_chunk_by_bytes;
+mod get_chunk_by_percent;
+mod get_chunk_count;
+mod get_column_names;
+mod get_context;
 mod get_file_encoding;
+mod get_file_info;
+mod get_filter_page;
+mod get_full_column;
+mod get_line_lengths;
+mod get_lines;
 mod get_parsing_information;
+mod get_raw_bytes;
+mod get_raw_chunk;
+mod match_timeline;
+mod nth_match;
 mod open_file;
+mod parse_delimited;
 mod parse_file;
+mod parse_stats;
+mod probe_formats;
+mod replace_to_file;
 mod search;
+mod search_bytes;
+mod set_encoding;
+mod set_follow;
+mod time_histogram;
 mod utils;
-pub use get_chunk::get_chunk;
+mod verify_index;
+pub use count_by_column::count_by_column;
+pub use detect_format::detect_format;
+pub use export_matches::export_matches;
+pub use extract::extract;
+pub use filter::filter;
+pub use filter_time_range::filter_time_range;
+pub use get_chunk::{ChunkOutput, get_chunk};
+pub use get_chunk_by_bytes::get_chunk_by_bytes;
+pub use get_chunk_by_percent::get_chunk_by_percent;
+pub use get_chunk_count::get_chunk_count;
+pub use get_column_names::column_names;
+pub use get_context::get_context;
 pub use get_file_encoding::get_file_encoding;
+pub use get_file_info::get_file_info;
+pub use get_filter_page::get_filter_page;
+pub use get_full_column::get_full_column;
+pub use get_line_lengths::get_line_lengths;
+pub use get_lines::get_lines;
 pub use get_parsing_information::get_parsing_information;
-pub use open_file::open_file;
+pub use get_raw_bytes::get_raw_bytes;
+pub use get_raw_chunk::get_raw_chunk;
+pub use match_timeline::match_timeline;
+pub use nth_match::nth_match;
+pub use open_file::{OpenMode, open_file, start_watcher};
+pub use parse_delimited::parse_delimited;
 pub use parse_file::parse_file;
-pub use search::search;
+pub use parse_stats::parse_stats;
+pub use probe_formats::probe_formats;
+pub use replace_to_file::replace_to_file;
+pub use search::{SearchOutput, SearchTarget, build_search_matcher, search};
+pub use search_bytes::{parse_hex, search_bytes};
+pub use set_encoding::set_encoding;
+pub use set_follow::set_follow;
+pub use time_histogram::time_histogram;
+pub use utils::{FilterMatches, LineParser, chunk_size};
+pub use verify_index::verify_index;
@@ -0,0 +1,82 @@
+use crate::{
+    services::{FileState, commands},
+    types::{ErrorCode, Response},
+};
+
+/// Lines scanned at a time, in file order
+const BATCH_SIZE: u64 = 10_000;
+
+/// Lines sent on each side of the match, like for OpenFile's goto_line
+const CONTEXT_LINES: u64 = 50;
+
+/// Finds the nth line (from 1) where the pattern matches anywhere in the line, like Filter
+/// The file is scanned in order and the scan stops at that line, which is returned in the
+/// middle of a chunk, as its focal_line
+pub fn nth_match(fs: &FileState, pattern: &str, n: u64, file_id: &Option<String>) -> Response {
+    if n == 0 {
+        return Response::Error {
+            message: String::from("n must be at least 1, the first match is n = 1"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    let matcher =
+        match commands::build_search_matcher(pattern, false, false, false, false, None, None) {
+            Ok(matcher) => matcher,
+            Err(message) => {
+                return Response::Error {
+                    message,
+                    code: Some(ErrorCode::InvalidRegex),
+                };
+            }
+        };
+
+    let processor = &fs.processor;
+    let line_count = processor.index.len() as u64;
+    let progress = commands::utils::LineProgress::start("Searching", line_count, file_id);
+
+    let mut matches = 0u64;
+    let mut found = None;
+    for start_line in (0..line_count).step_by(BATCH_SIZE as usize) {
+        let end_line = (start_line + BATCH_SIZE - 1).min(line_count - 1);
+        let lines = match processor.scan_lines_range(start_line, end_line) {
+            Ok(lines) => lines,
+            // A skipped batch would make every later match number wrong
+            Err(err) => return err.into(),
+        };
+
+        found = (start_line..)
+            .zip(&lines)
+            .filter(|(_, line)| matcher.is_match(line))
+            .map(|(line, _)| line)
+            .find(|_| {
+                matches += 1;
+                matches == n
+            });
+
+        progress.advance(end_line - start_line + 1);
+        if found.is_some() {
+            break;
+        }
+    }
+    progress.finish();
+
+    let Some(line) = found else {
+        return Response::Error {
+            message: format!(
+                "The pattern matches {} lines, there's no match number {}",
+                matches, n
+            ),
+            code: Some(ErrorCode::OutOfBounds),
+        };
+    };
+
+    commands::get_context(
+        processor,
+        line,
+        CONTEXT_LINES,
+        CONTEXT_LINES,
+        &fs.line_parser,
+        fs.nbr_columns,
+    )
+}
@@ -1,87 +1,453 @@
 use std::{
-    sync::{
-        Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
-    },
-    thread::{self, JoinHandle},
+    sync::{Arc, Mutex, atomic::Ordering},
+    thread,
     time::Duration,
 };
 
 use crate::{
-    services::{FileProcessor, FileState, commands::utils, file_processor::FileChangeType},
-    types::Response,
+    services::{
+        self, FileProcessor, FileState, OpenCancel, Session, WatchSettings,
+        commands::{self, utils},
+        commands_processor,
+        file_processor::{DEFAULT_MAX_FILE_SIZE, EncodingSource, FileChangeType},
+    },
+    types::{LogFormat, NotifyMode, OpenFileOptions, Response, SessionResponse},
 };
 
+/// LinesAdded events are split so none carries more lines than this
+const DEFAULT_MAX_LINES_PER_EVENT: usize = 1000;
+
+/// Lines sent on each side of goto_line
+const GOTO_LINE_CONTEXT: u64 = 50;
+
+/// With debounce, held lines are sent after this many ticks even if the file keeps growing
+const MAX_DEBOUNCE_TICKS: u32 = 3;
+
+/// The watcher gives up after this many failed refreshes in a row
+const MAX_REFRESH_FAILURES: u32 = 3;
+
+/// Which part of the file OpenFile indexes
+#[derive(Debug, Clone, Copy)]
+pub enum OpenMode {
+    /// The whole file
+    Whole,
+    /// Only the lines written after the open (OpenTail)
+    Tail,
+    /// Only the lines starting in [start_byte, end_byte) (OpenWindow)
+    Window(u64, u64),
+}
+
+/// New lines seen by the watcher but not sent yet
+struct PendingLines {
+    old_line_count: u64,
+    lines: Vec<String>,
+    ticks: u32,
+}
+
+/// Open and index a file, then start watching it
+/// With `tail`, the file's current content isn't indexed (see FileProcessor::new_tail)
 pub fn open_file(
     path: &str,
-    file_state: &mut Arc<Mutex<Option<FileState>>>,
-    watcher_handle: &mut Option<JoinHandle<()>>,
-    should_stop: &Arc<AtomicBool>,
+    session: &mut Session,
+    file_id: Option<String>,
+    options: OpenFileOptions,
+    mode: OpenMode,
+    open_cancel: &OpenCancel,
 ) -> Response {
-    if let Some(handle) = watcher_handle.take() {
-        should_stop.store(true, Ordering::Relaxed); //Hey thread, stop what you're doing.
-        handle.join().expect("Thread panicked"); //i'm waiting for you...
-        should_stop.store(false, Ordering::Relaxed); //reset the stop signal for another use.
-    }
+    session.stop_watcher();
+
+    let record_separator = options.record_separator.unwrap_or(b'\n');
+    let size_limit =
+        (!options.allow_large).then(|| options.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE));
+    let encoding_source = match &options.assume_encoding {
+        Some(label) => EncodingSource::Assume(label.clone()),
+        None => EncodingSource::Detect(options.detect_bytes),
+    };
+    // Only an open registered with open_cancel can be cancelled
+    let (processor, cancelled) = match mode {
+        OpenMode::Tail => (
+            FileProcessor::new_tail(path, encoding_source, record_separator),
+            false,
+        ),
+        OpenMode::Window(start_byte, end_byte) => {
+            open_cancel.start(commands_processor::session_key(&file_id));
+            let processor = FileProcessor::new_window(
+                path,
+                encoding_source,
+                record_separator,
+                (start_byte, end_byte),
+                size_limit,
+                open_cancel.flag(),
+            );
+            (processor, open_cancel.finish())
+        }
+        OpenMode::Whole => {
+            report_compression(path, &file_id);
+
+            open_cancel.start(commands_processor::session_key(&file_id));
+            let processor = FileProcessor::new(
+                path,
+                encoding_source,
+                size_limit,
+                record_separator,
+                open_cancel.flag(),
+            );
+            (processor, open_cancel.finish())
+        }
+    };
 
-    let processor = match FileProcessor::new(path) {
+    let mut processor = match processor {
         Ok(p) => p,
+        Err(_) if cancelled => {
+            return Response::Info {
+                message: String::from("open cancelled"),
+            };
+        }
         Err(err) => {
             let response = Response::Error {
-                message: format!("Something went wrong when indexing the file: {}", err),
+                message: format!(
+                    "Something went wrong when indexing the file: {}",
+                    err.message
+                ),
+                code: Some(err.code),
             };
             return response;
         }
     };
 
-    *file_state = Arc::new(Mutex::new(Some(FileState {
+    let compressed = processor.compression.is_some();
+
+    if options.keep_handle_open && !compressed {
+        processor.hold_file_handle();
+    }
+
+    if let Some(max_line_bytes) = options.max_line_bytes {
+        processor.max_line_bytes = max_line_bytes;
+    }
+    processor.decode_policy = options.decode_policy;
+
+    session.file_state = Arc::new(Mutex::new(Some(FileState {
         processor,
-        regex_pattern: None,
+        log_format: None,
+        line_parser: None,
         nbr_columns: None,
+        filter: None,
     })));
 
-    let cloned_file_state = Arc::clone(file_state);
-    let stop_flag = Arc::clone(should_stop);
-    *watcher_handle = Some(thread::spawn(move || {
+    session.watch_settings = WatchSettings {
+        heartbeat: options.heartbeat,
+        debounce: options.debounce,
+        max_lines_per_event: options
+            .max_lines_per_event
+            .unwrap_or(DEFAULT_MAX_LINES_PER_EVENT)
+            .max(1),
+        notify: options.notify,
+    };
+
+    // A decompressed copy never changes, and a window doesn't grow, there's nothing to watch
+    let window = matches!(mode, OpenMode::Window(..));
+    if options.follow.unwrap_or(true) && !compressed && !window {
+        start_watcher(session, file_id.clone());
+    }
+
+    let opened = opened_response(session);
+    // FileOpened goes out first, the chunk around goto_line is the answer to OpenFile
+    match options
+        .goto_line
+        .and_then(|line| goto_line_chunk(session, line))
+    {
+        Some(chunk) => {
+            send(&file_id, opened);
+            chunk
+        }
+        None => opened,
+    }
+}
+
+/// The lines around `line`, clamped to the last line of the file
+/// Returns None for an empty file, where there's nothing to show
+fn goto_line_chunk(session: &Session, line: u64) -> Option<Response> {
+    let guard = session.file_state.lock().unwrap();
+    let fs = guard.as_ref().unwrap();
+    let line_count = fs.processor.index.len() as u64;
+    if line_count == 0 {
+        return None;
+    }
+
+    Some(commands::get_context(
+        &fs.processor,
+        line.min(line_count - 1),
+        GOTO_LINE_CONTEXT,
+        GOTO_LINE_CONTEXT,
+        &fs.line_parser,
+        fs.nbr_columns,
+    ))
+}
+
+/// Spawn the thread that checks the file every 5 seconds and reports its changes
+pub fn start_watcher(session: &mut Session, file_id: Option<String>) {
+    let cloned_file_state = Arc::clone(&session.file_state);
+    let stop_flag = Arc::clone(&session.should_stop);
+    let WatchSettings {
+        heartbeat,
+        debounce,
+        max_lines_per_event,
+        notify,
+    } = session.watch_settings;
+    session.watcher_handle = Some(thread::spawn(move || {
+        let mut pending: Option<PendingLines> = None;
+        let mut failures = 0;
+
         loop {
             thread::sleep(Duration::from_secs(5));
 
             if stop_flag.load(Ordering::Relaxed) {
+                stopped(
+                    &cloned_file_state,
+                    pending.take(),
+                    &file_id,
+                    max_lines_per_event,
+                    None,
+                );
                 break; // Exit the loop
             }
 
-            let mut file_state_guard = cloned_file_state.lock().unwrap();
-            if let Some(ref mut fp) = *file_state_guard
-                && let Ok(Some((change_type, old_count, new_count, new_lines))) =
-                    fp.processor.refresh_if_needed()
-            {
-                let response = match change_type {
-                    FileChangeType::Truncated => Response::FileTruncated {
-                        line_count: new_count,
-                    },
+            // A command panicked while holding the file state, stopped reports it
+            let Ok(mut file_state_guard) = cloned_file_state.lock() else {
+                stopped(
+                    &cloned_file_state,
+                    pending.take(),
+                    &file_id,
+                    max_lines_per_event,
+                    None,
+                );
+                break;
+            };
+            let Some(ref mut fp) = *file_state_guard else {
+                continue;
+            };
+
+            let refreshed = fp.processor.refresh_if_needed();
+            if refreshed.is_ok() {
+                failures = 0;
+            }
+
+            let responses = match refreshed {
+                Ok(Some((change_type, old_count, new_count, new_lines))) => match change_type {
+                    FileChangeType::Truncated(messages) => {
+                        // Held lines and filtered line numbers belong to the old content
+                        pending = None;
+                        fp.filter = None;
+
+                        // Warnings about the new content's encoding go out whatever the notify mode
+                        let mut responses: Vec<Response> = messages
+                            .into_iter()
+                            .map(|message| Response::Info { message })
+                            .collect();
+                        if notify != NotifyMode::None {
+                            responses.push(Response::FileTruncated {
+                                line_count: new_count,
+                                trailing_newline: fp.processor.has_trailing_newline(),
+                            });
+                        }
+                        responses
+                    }
+                    // The new lines are indexed, the client reads them when it wants to
+                    FileChangeType::LinesAdded if notify != NotifyMode::All => continue,
                     FileChangeType::LinesAdded => {
-                        // Parse the new lines using the same logic as GetChunk
-                        let parsed_lines = utils::parse_data(
-                            &fp.regex_pattern,
-                            fp.nbr_columns,
-                            &new_lines,
-                            old_count,
-                            false, // Don't show parsing errors for live tail
-                        );
-
-                        Response::LinesAdded {
+                        let held = pending.get_or_insert_with(|| PendingLines {
                             old_line_count: old_count,
-                            new_line_count: new_count,
-                            new_lines: parsed_lines,
+                            lines: Vec::new(),
+                            ticks: 0,
+                        });
+                        held.lines.extend(new_lines);
+                        held.ticks += 1;
+
+                        // Keep waiting while the burst is small and recent
+                        if debounce
+                            && held.lines.len() < max_lines_per_event
+                            && held.ticks < MAX_DEBOUNCE_TICKS
+                        {
+                            continue;
                         }
+
+                        let held = pending.take().unwrap();
+                        lines_added_events(fp, held, max_lines_per_event)
                     }
+                },
+                // The burst is over, send what was held back
+                Ok(None) if pending.is_some() => {
+                    lines_added_events(fp, pending.take().unwrap(), max_lines_per_event)
+                }
+                // Let the client know the watcher is alive even when nothing changed
+                Ok(None) if heartbeat => vec![Response::NoChange {
+                    line_count: fp.processor.index.len() as u64,
+                }],
+                Ok(None) => continue,
+                // The file may be briefly unreadable (e.g. mid-rotation), give it a few ticks
+                Err(message) => {
+                    failures += 1;
+                    if failures < MAX_REFRESH_FAILURES {
+                        continue;
+                    }
+
+                    drop(file_state_guard);
+                    stopped(
+                        &cloned_file_state,
+                        pending.take(),
+                        &file_id,
+                        max_lines_per_event,
+                        Some(format!(
+                            "The file couldn't be refreshed {} times in a row: {}",
+                            failures, message
+                        )),
+                    );
+                    break;
+                }
+            };
+
+            // Tag the events so the client can route them to the right pane
+            for response in responses {
+                let message = SessionResponse {
+                    file_id: file_id.clone(),
+                    response,
                 };
-                println!("{}", serde_json::to_string(&response).unwrap());
+                services::output::send(&message);
             }
         }
     }));
+}
+
+/// Report the end of the watcher: lines still held back by the debounce,
+/// then TailStopped with the number of lines known when it stopped
+/// `error` says why a watcher stopped on its own, a poisoned file state is reported
+/// the same way (its held lines are dropped, they can't be trusted)
+fn stopped(
+    file_state: &Mutex<Option<FileState>>,
+    pending: Option<PendingLines>,
+    file_id: &Option<String>,
+    max_lines: usize,
+    error: Option<String>,
+) {
+    let (file_state_guard, pending, error) = match file_state.lock() {
+        Ok(guard) => (guard, pending, error),
+        Err(poisoned) => (
+            poisoned.into_inner(),
+            None,
+            Some(String::from(
+                "A command panicked while holding the file, it is no longer watched",
+            )),
+        ),
+    };
+
+    let mut responses = Vec::new();
+    if let (Some(fp), Some(held)) = (file_state_guard.as_ref(), pending) {
+        responses = lines_added_events(fp, held, max_lines);
+    }
+    responses.push(Response::TailStopped {
+        total_lines: file_state_guard
+            .as_ref()
+            .map_or(0, |fp| fp.processor.index.len() as u64),
+        error,
+    });
+
+    for response in responses {
+        send(file_id, response);
+    }
+}
+
+/// Turn held lines into LinesAdded events of at most max_lines lines each,
+/// every event continuing where the previous one stopped
+fn lines_added_events(fp: &FileState, pending: PendingLines, max_lines: usize) -> Vec<Response> {
+    let trailing_newline = fp.processor.has_trailing_newline();
+    let mut old_line_count = pending.old_line_count;
+
+    pending
+        .lines
+        .chunks(max_lines)
+        .map(|lines| {
+            // Parse the new lines using the same logic as GetChunk
+            let new_lines = utils::parse_data(
+                &fp.line_parser,
+                fp.nbr_columns,
+                fp.processor.strip_ansi(),
+                lines,
+                old_line_count,
+                false, // Don't show parsing errors for live tail
+            );
+
+            let new_line_count = old_line_count + lines.len() as u64;
+            let response = Response::LinesAdded {
+                old_line_count,
+                new_line_count,
+                new_lines,
+                trailing_newline,
+            };
+            old_line_count = new_line_count;
+            response
+        })
+        .collect()
+}
+
+fn opened_response(session: &Session) -> Response {
+    let guard = session.file_state.lock().unwrap();
+    let fs = guard.as_ref().unwrap();
+    let processor = &fs.processor;
+    let line_count = processor.index.len() as u64;
+
+    // The span of the log, from two line reads rather than a scan
+    let (first_timestamp, last_timestamp) = if line_count > 0 {
+        let log_format = utils::timestamp::timestamp_format(fs);
+        (
+            line_timestamp(fs, &log_format, 0),
+            line_timestamp(fs, &log_format, line_count - 1),
+        )
+    } else {
+        (None, None)
+    };
+
+    Response::FileOpened {
+        line_count,
+        trailing_newline: processor.has_trailing_newline(),
+        skipped_bytes: (processor.base_offset > 0).then_some(processor.base_offset),
+        window_end: processor.end_offset,
+        warnings: processor.warnings.clone(),
+        first_timestamp,
+        last_timestamp,
+    }
+}
+
+/// Timestamp of one line in ms since the epoch
+fn line_timestamp(fs: &FileState, log_format: &LogFormat, line: u64) -> Option<i64> {
+    let text = fs.processor.read_lines_range(line, line).ok()?.pop()?;
+    utils::timestamp::parse_timestamp(log_format, &text)?.epoch_millis()
+}
+
+/// Tell the client about a compressed file before the (long) decompression and indexing start,
+/// with an estimate of its decompressed size to use as a progress target
+fn report_compression(path: &str, file_id: &Option<String>) {
+    let Ok(mut file) = services::open_checked(path) else {
+        return; // FileProcessor::new reports the error
+    };
+    let Ok(Some(compression)) = services::detect_compression(&mut file) else {
+        return;
+    };
+
+    let estimated_size = services::estimate_uncompressed_size(&mut file, compression)
+        .ok()
+        .flatten();
+
+    // Files that can't be decompressed are warned about in FileOpened
+    send(
+        file_id,
+        Response::CompressedFile {
+            compression: compression.name().to_string(),
+            estimated_size,
+        },
+    );
+}
 
-    let guard = file_state.lock().unwrap();
-    let line_count = guard.as_ref().unwrap().processor.index.len() as u64;
-    Response::FileOpened { line_count }
+/// Print a response right away, before the one returned by open_file
+fn send(file_id: &Option<String>, response: Response) {
+    services::output::send_to(file_id, response);
 }
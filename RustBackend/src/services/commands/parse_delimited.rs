@@ -0,0 +1,63 @@
+use crate::{
+    services::{FileState, commands::utils::LineParser},
+    types::{ErrorCode, LogFormat, Response},
+};
+
+/// Splits lines on a delimiter instead of a regex, for CSV/TSV-like logs
+/// nbr_columns can't be 0: a split line has at least one field, no line could match
+pub fn parse_delimited(fs: &mut FileState, delimiter: char, nbr_columns: Option<u8>) -> Response {
+    if delimiter == '\n' || delimiter == '\r' {
+        return Response::Error {
+            message: String::from("The delimiter can't be a line break"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    if nbr_columns == Some(0) {
+        return Response::Error {
+            message: String::from(
+                "nbr_columns must be at least 1, a split line always has a field",
+            ),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    }
+
+    // The last filter's distinct values were columns of the old format
+    fs.filter = None;
+    fs.log_format = Some(LogFormat::Other);
+    fs.line_parser = Some(LineParser::Delimited(delimiter));
+    fs.nbr_columns = nbr_columns;
+
+    Response::ParsingInformation {
+        log_format: LogFormat::Other,
+        sampled: None,
+        matched: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_files::TempFile;
+
+    #[test]
+    fn parse_delimited_rejects_zero_columns() {
+        let file = TempFile::new(b"a,b,c\n");
+        let file_state = file.file_state();
+        let mut guard = file_state.lock().unwrap();
+        let fs = guard.as_mut().unwrap();
+
+        let response = parse_delimited(fs, ',', Some(0));
+        assert!(matches!(
+            response,
+            Response::Error {
+                code: Some(ErrorCode::InvalidArgument),
+                ..
+            }
+        ));
+        assert!(fs.line_parser.is_none());
+
+        parse_delimited(fs, ',', Some(3));
+        assert!(fs.line_parser.is_some());
+    }
+}
@@ -2,26 +2,228 @@ use std::sync::{Arc, Mutex};
 
 use regex::Regex;
 
-use crate::services::commands::utils::log_format_patterns;
-use crate::types::Response;
-use crate::{services::FileState, types::LogFormat};
+use crate::services::commands::utils::{self, LineParser, log_format_patterns};
+use crate::types::{ErrorCode, Response};
+use crate::{
+    services::{FileProcessor, FileState},
+    types::LogFormat,
+};
+
+/// Upper bound on sample_lines to keep ParseFile fast
+const MAX_SAMPLE_LINES: u64 = 10_000;
+
+/// How far from the top of a W3C log the #Fields directive is looked for
+const W3C_HEADER_LINES: u64 = 100;
 
 pub fn parse_file(
     file_state: Arc<Mutex<Option<FileState>>>,
     log_format: LogFormat,
     pattern: Option<String>,
     nbr_columns: Option<u8>,
+    sample_lines: Option<u64>,
+    strip_ansi: bool,
+    trim: bool,
 ) -> Response {
-    let final_regex = pattern
-        .and_then(|re_str| Regex::new(&re_str).ok())
-        .or_else(|| log_format_patterns::get_pattern(&log_format));
+    let custom_regex = pattern.and_then(|re_str| Regex::new(&re_str).ok());
+    let use_w3c_fields = custom_regex.is_none() && log_format == LogFormat::W3CExtended;
+    let use_docker_json = custom_regex.is_none() && log_format == LogFormat::DockerJson;
 
-    let final_columns = nbr_columns.or_else(|| log_format_patterns::get_column_count(&log_format));
+    let mut final_parser = custom_regex
+        .or_else(|| log_format_patterns::get_pattern(&log_format))
+        .map(|regex| LineParser::Regex { regex, trim });
 
+    // Docker lines are JSON objects, read by key rather than with a pattern
+    if use_docker_json {
+        final_parser = Some(LineParser::DockerJson);
+    }
+
+    // Lines can't have more columns than the pattern has groups, they'd all fail to parse
+    if let (Some(LineParser::Regex { regex, .. }), Some(expected)) = (&final_parser, nbr_columns) {
+        let groups = regex.captures_len() - 1;
+        if expected as usize > groups {
+            return Response::Error {
+                message: format!(
+                    "nbr_columns ({}) is larger than the number of capture groups in the pattern ({}), no line could match",
+                    expected, groups
+                ),
+                code: Some(ErrorCode::InvalidArgument),
+            };
+        }
+    }
+
+    let mut final_columns =
+        nbr_columns.or_else(|| log_format_patterns::get_column_count(&log_format));
+
+    let mut sample = None;
     if let Some(fs) = file_state.lock().unwrap().as_mut() {
-        fs.regex_pattern = final_regex;
+        // Set first, so the #Fields directive and the sample are read like every other line
+        fs.processor.set_strip_ansi(strip_ansi);
+
+        // The #Fields directive describes the actual columns, better than the generic pattern
+        if use_w3c_fields && let Some(names) = find_w3c_fields(&fs.processor) {
+            final_columns = nbr_columns.or(Some(names.len() as u8));
+            final_parser = Some(LineParser::W3CFields(names));
+        }
+
+        if let (Some(parser), Some(count)) = (&final_parser, sample_lines) {
+            sample = Some(sample_file(&fs.processor, parser, final_columns, count));
+        }
+
+        // Nothing to parse with (Other without a pattern): back to whole raw lines,
+        // a column count alone would only get in the way of column lookups
+        if final_parser.is_none() {
+            final_columns = None;
+        }
+        // The last filter's distinct values were columns of the old format
+        fs.filter = None;
+        fs.log_format = Some(log_format.clone());
+        fs.line_parser = final_parser;
         fs.nbr_columns = final_columns;
     }
 
-    Response::ParsingInformation { log_format }
+    let (sampled, matched) = sample.unzip();
+    Response::ParsingInformation {
+        log_format,
+        sampled,
+        matched,
+    }
+}
+
+/// Looks for a #Fields directive near the top of the file
+/// Ignored when it declares more columns than nbr_columns can hold
+fn find_w3c_fields(processor: &FileProcessor) -> Option<Vec<String>> {
+    let line_count = processor.index.len() as u64;
+    if line_count == 0 {
+        return None;
+    }
+
+    let lines = processor
+        .read_lines_range(0, W3C_HEADER_LINES.min(line_count) - 1)
+        .ok()?;
+
+    lines
+        .iter()
+        .find_map(|line| {
+            log_format_patterns::parse_w3c_fields(&utils::clean_line(line, processor.strip_ansi()))
+        })
+        .filter(|names| names.len() <= u8::MAX as usize)
+}
+
+/// Runs the first `count` lines through the parser
+/// Returns how many lines were sampled and how many of them parsed
+fn sample_file(
+    processor: &FileProcessor,
+    parser: &LineParser,
+    nbr_columns: Option<u8>,
+    count: u64,
+) -> (u64, u64) {
+    let count = count.min(MAX_SAMPLE_LINES);
+    if count == 0 {
+        return (0, 0);
+    }
+
+    let lines = match processor.read_lines_range(0, count - 1) {
+        Ok(lines) => lines,
+        Err(_) => return (0, 0),
+    };
+
+    let matched = lines
+        .iter()
+        .filter(|line| {
+            let line = utils::clean_line(line, processor.strip_ansi());
+            utils::parse_line(parser, nbr_columns, &line).is_some()
+        })
+        .count();
+
+    (lines.len() as u64, matched as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{
+        commands::{get_chunk, get_raw_chunk},
+        test_files::TempFile,
+    };
+
+    const CLF_LINE: &str =
+        r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326"#;
+
+    fn first_row(file_state: &Arc<Mutex<Option<FileState>>>) -> Vec<String> {
+        let guard = file_state.lock().unwrap();
+        let fs = guard.as_ref().unwrap();
+        match get_chunk(
+            &fs.processor,
+            0,
+            0,
+            &fs.line_parser,
+            fs.nbr_columns,
+            Default::default(),
+        ) {
+            Response::Chunk { mut data, .. } => data.remove(0),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn parse_format_on_then_off() {
+        let file = TempFile::new(format!("{}\n", CLF_LINE).as_bytes());
+        let file_state = file.file_state();
+
+        parse_file(
+            file_state.clone(),
+            LogFormat::CommonLogFormat,
+            None,
+            None,
+            None,
+            false,
+            false,
+        );
+        assert_eq!(first_row(&file_state).len(), 8);
+
+        // Other without a pattern clears the format, lines come back whole
+        parse_file(
+            file_state.clone(),
+            LogFormat::Other,
+            None,
+            None,
+            None,
+            false,
+            false,
+        );
+        {
+            let guard = file_state.lock().unwrap();
+            let fs = guard.as_ref().unwrap();
+            assert!(fs.line_parser.is_none());
+            assert_eq!(fs.nbr_columns, None);
+        }
+        assert_eq!(first_row(&file_state), [CLF_LINE]);
+    }
+
+    #[test]
+    fn strip_ansi_only_in_parsed_data() {
+        let file = TempFile::new(b"\x1b[31mERROR\x1b[0m disk full\n");
+        let file_state = file.file_state();
+
+        parse_file(
+            file_state.clone(),
+            LogFormat::Other,
+            None,
+            None,
+            None,
+            true,
+            false,
+        );
+        assert_eq!(first_row(&file_state), ["ERROR disk full"]);
+
+        // The line as read keeps its colors, for raw chunks and exports
+        let guard = file_state.lock().unwrap();
+        let fs = guard.as_ref().unwrap();
+        let raw_line = "\x1b[31mERROR\x1b[0m disk full";
+        assert_eq!(fs.processor.read_line(0).unwrap(), raw_line);
+        match get_raw_chunk(&fs.processor, 0, 0) {
+            Response::Chunk { data, .. } => assert_eq!(data, [[raw_line]]),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
 }
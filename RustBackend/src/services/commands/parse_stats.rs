@@ -0,0 +1,59 @@
+use crate::{
+    services::{FileState, commands::utils},
+    types::{ErrorCode, Response},
+};
+
+/// How many lines of a chunk parsed, had the wrong number of columns, or didn't match
+#[derive(Default, Clone, Copy)]
+struct ParseCounts {
+    matched: u64,
+    column_mismatch: u64,
+    unmatched: u64,
+}
+
+/// Runs every line of the file through the current format
+/// Unlike ParseFile's sample_lines, the counts cover the whole file
+pub fn parse_stats(fs: &FileState, file_id: &Option<String>) -> Response {
+    let Some(parser) = &fs.line_parser else {
+        return Response::Error {
+            message: String::from("No format is set, use ParseFile or ParseDelimited first"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    };
+
+    let (counts, complete) = utils::scan_chunks(
+        &fs.processor,
+        "Parsing",
+        file_id,
+        |_, lines| count_chunk(fs, parser, lines),
+        |a, b| ParseCounts {
+            matched: a.matched + b.matched,
+            column_mismatch: a.column_mismatch + b.column_mismatch,
+            unmatched: a.unmatched + b.unmatched,
+        },
+    );
+
+    Response::ParseStats {
+        total: fs.processor.index.len() as u64,
+        matched: counts.matched,
+        column_mismatch: counts.column_mismatch,
+        unmatched: counts.unmatched,
+        complete,
+    }
+}
+
+fn count_chunk(fs: &FileState, parser: &utils::LineParser, lines: &[String]) -> ParseCounts {
+    let mut counts = ParseCounts::default();
+    for line in lines {
+        match utils::split_line(parser, &utils::clean_line(line, fs.processor.strip_ansi())) {
+            None => counts.unmatched += 1,
+            // Same rule as parse_line: the count is only checked when nbr_columns is set
+            Some(groups) if fs.nbr_columns.is_some_and(|n| groups.len() != n as usize) => {
+                counts.column_mismatch += 1
+            }
+            Some(_) => counts.matched += 1,
+        }
+    }
+
+    counts
+}
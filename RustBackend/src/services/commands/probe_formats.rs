@@ -0,0 +1,66 @@
+use crate::{
+    services::{commands::utils::log_format_patterns, file_processor::FileProcessor},
+    types::{FormatScore, LogFormat, Response},
+};
+
+/// Lines sampled when the client doesn't say
+const DEFAULT_SAMPLE_LINES: u64 = log_format_patterns::LEADING_LINES;
+
+/// Upper bound on sample_lines to keep ProbeFormats fast
+const MAX_SAMPLE_LINES: u64 = 10_000;
+
+/// Scores every built-in format on the first non-blank lines among the first `sample_lines`
+/// Each score is the fraction of those lines the format's pattern matches, best first
+/// A leading BOM and indentation are ignored, like when the format is detected
+pub fn probe_formats(processor: &FileProcessor, sample_lines: Option<u64>) -> Response {
+    let count = sample_lines
+        .unwrap_or(DEFAULT_SAMPLE_LINES)
+        .min(MAX_SAMPLE_LINES)
+        .min(processor.index.len() as u64);
+
+    let lines = match count {
+        0 => Vec::new(),
+        _ => match processor.read_lines_range(0, count - 1) {
+            Ok(lines) => lines,
+            Err(err) => return err.into(),
+        },
+    };
+    let entries: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_start_matches('\u{FEFF}').trim_start())
+        .filter(|line| !line.trim_end().is_empty())
+        .collect();
+
+    let mut formats: Vec<FormatScore> = log_format_patterns::BUILT_IN_FORMATS
+        .iter()
+        .map(|format| {
+            let matched = count_matches(format, &entries);
+            FormatScore {
+                log_format: format.clone(),
+                score: match entries.len() {
+                    0 => 0.0,
+                    sampled => matched as f32 / sampled as f32,
+                },
+            }
+        })
+        .collect();
+
+    // Stable, so equal scores stay in detection order
+    formats.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Response::FormatProbe {
+        formats,
+        sampled: entries.len() as u64,
+    }
+}
+
+fn count_matches(format: &LogFormat, entries: &[&str]) -> usize {
+    match log_format_patterns::get_pattern(format) {
+        Some(pattern) => entries.iter().filter(|line| pattern.is_match(line)).count(),
+        // Docker lines are JSON objects, they aren't matched with a regex
+        None => entries
+            .iter()
+            .filter(|line| log_format_patterns::parse_docker_json(line).is_some())
+            .count(),
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{BufWriter, Write};
+
+use encoding_rs::Encoding;
+
+use crate::{
+    services::{
+        self,
+        commands::{export_matches::BATCH_SIZE, utils::LineProgress},
+        file_processor::FileProcessor,
+        write_error,
+    },
+    types::{CodedError, ErrorCode, Response},
+};
+
+/// The compiled pattern, matched the way the file's encoding allows
+enum Replacer {
+    /// ASCII-compatible files, matched on their bytes so invalid UTF-8 is kept as is
+    Bytes(regex::bytes::Regex),
+    /// UTF-16 files, each line is decoded, replaced and encoded back
+    Utf16(regex::Regex, &'static Encoding),
+}
+
+/// Writes a copy of the indexed lines to out_path with every match of pattern replaced
+/// Lines without a match are copied byte for byte, changed lines keep their encoding,
+/// line ending and BOM. The opened file is never written to
+pub fn replace_to_file(
+    processor: &FileProcessor,
+    pattern: &str,
+    replacement: &str,
+    out_path: &str,
+    file_id: &Option<String>,
+) -> Response {
+    let replacer = match processor.utf16_encoding() {
+        None => regex::bytes::Regex::new(pattern).map(Replacer::Bytes),
+        Some(encoding) => regex::Regex::new(pattern).map(|regex| Replacer::Utf16(regex, encoding)),
+    };
+    let replacer = match replacer {
+        Ok(replacer) => replacer,
+        Err(e) => {
+            return Response::Error {
+                message: format!("Invalid regex pattern: {}", e),
+                code: Some(ErrorCode::InvalidRegex),
+            };
+        }
+    };
+
+    let file = match services::create_checked_output(out_path, &processor.file_path) {
+        Ok(file) => file,
+        Err(err) => return err.into(),
+    };
+    let mut writer = BufWriter::new(file);
+
+    let line_count = processor.index.len() as u64;
+    let progress = LineProgress::start("Replacing", line_count, file_id);
+
+    let mut lines_changed = 0u64;
+    let mut replacements = 0u64;
+    for start_line in (0..line_count).step_by(BATCH_SIZE as usize) {
+        let end_line = (start_line + BATCH_SIZE - 1).min(line_count - 1);
+        let result = replace_batch(
+            processor,
+            &replacer,
+            replacement,
+            &mut writer,
+            start_line,
+            end_line,
+        );
+
+        match result {
+            Ok((changed, replaced)) => {
+                lines_changed += changed;
+                replacements += replaced;
+            }
+            Err(err) => return err.into(),
+        }
+        progress.advance(end_line - start_line + 1);
+    }
+    progress.finish();
+
+    if let Err(e) = writer.flush() {
+        return write_error(e).into();
+    }
+
+    Response::Replaced {
+        lines_written: line_count,
+        lines_changed,
+        replacements,
+    }
+}
+
+/// Writes the lines from start_line to end_line, returns the lines changed and the replacements
+fn replace_batch(
+    processor: &FileProcessor,
+    replacer: &Replacer,
+    replacement: &str,
+    writer: &mut impl Write,
+    start_line: u64,
+    end_line: u64,
+) -> Result<(u64, u64), CodedError> {
+    let (bytes, start_pos, _) = processor.read_raw_range(start_line, end_line, u64::MAX)?;
+
+    let mut lines_changed = 0u64;
+    let mut replacements = 0u64;
+    for line in start_line..=end_line {
+        let start = (processor.line_start_byte(line) - start_pos) as usize;
+        let end = (processor.line_end_byte(line) - start_pos) as usize;
+        let text_end = start + processor.line_byte_len(line) as usize;
+        let (text, ending) = (&bytes[start..text_end], &bytes[text_end..end]);
+
+        // A BOM at the start of the file isn't part of the first line's text
+        let bom = if processor.line_start_byte(line) == 0 {
+            bom_len(text, replacer)
+        } else {
+            0
+        };
+        let (bom, text) = text.split_at(bom);
+
+        match replace_line(replacer, text, replacement) {
+            Some((replaced, count)) => {
+                lines_changed += 1;
+                replacements += count;
+                writer.write_all(bom).map_err(write_error)?;
+                writer.write_all(&replaced).map_err(write_error)?;
+                writer.write_all(ending).map_err(write_error)?;
+            }
+            None => writer.write_all(&bytes[start..end]).map_err(write_error)?,
+        }
+    }
+
+    Ok((lines_changed, replacements))
+}
+
+/// The replaced bytes of a line's text and the number of replacements, None when nothing matched
+/// A trailing \r is kept out of the match, so `$` matches at the end of CRLF lines
+fn replace_line(replacer: &Replacer, text: &[u8], replacement: &str) -> Option<(Vec<u8>, u64)> {
+    match replacer {
+        Replacer::Bytes(regex) => {
+            let (text, cr) = match text.strip_suffix(b"\r") {
+                Some(text) => (text, &b"\r"[..]),
+                None => (text, &b""[..]),
+            };
+            let count = regex.find_iter(text).count() as u64;
+            if count == 0 {
+                return None;
+            }
+
+            let mut replaced = regex.replace_all(text, replacement.as_bytes()).into_owned();
+            replaced.extend_from_slice(cr);
+            Some((replaced, count))
+        }
+        Replacer::Utf16(regex, encoding) => {
+            // Lines with invalid UTF-16 are copied as is rather than re-encoded with U+FFFD
+            let decoded = encoding.decode_without_bom_handling_and_without_replacement(text)?;
+            let (text, cr) = match decoded.strip_suffix('\r') {
+                Some(text) => (text, "\r"),
+                None => (&*decoded, ""),
+            };
+            let count = regex.find_iter(text).count() as u64;
+            if count == 0 {
+                return None;
+            }
+
+            let replaced = regex.replace_all(text, replacement) + cr;
+            let units = replaced.encode_utf16();
+            let bytes = if *encoding == encoding_rs::UTF_16LE {
+                units.flat_map(u16::to_le_bytes).collect()
+            } else {
+                units.flat_map(u16::to_be_bytes).collect()
+            };
+            Some((bytes, count))
+        }
+    }
+}
+
+/// Length of the byte order mark text starts with, 0 when it has none
+fn bom_len(text: &[u8], replacer: &Replacer) -> usize {
+    let bom: &[u8] = match replacer {
+        Replacer::Bytes(_) => b"\xEF\xBB\xBF",
+        Replacer::Utf16(_, encoding) if *encoding == encoding_rs::UTF_16LE => b"\xFF\xFE",
+        Replacer::Utf16(..) => b"\xFE\xFF",
+    };
+    if text.starts_with(bom) { bom.len() } else { 0 }
+}
@@ -1,128 +1,651 @@
+use memchr::memmem;
 use rayon::prelude::*;
-use std::sync::{
-    Arc,
-    atomic::{AtomicU8, AtomicUsize, Ordering},
+use std::{
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicUsize, Ordering},
+    },
+    time::Instant,
 };
 
-use crate::services::commands::utils;
+use crate::services::commands::utils::{self, LineParser, log_format_patterns};
 use crate::{
-    services::FileProcessor,
-    types::{Response, SearchMatch},
+    services::{self, FileProcessor},
+    types::{CodedError, ErrorCode, Response, SearchMatch, SearchMode},
 };
 
+/// Matches kept per line, a pattern matching almost everywhere would flood the results
+const MAX_MATCHES_PER_LINE: usize = 100;
+
+/// Searches of fewer lines than this (a few default chunks) finish too fast for a progress bar,
+/// they send no SearchProgress, only their results
+pub(super) const QUIET_SEARCH_LINES: usize = 4 * utils::DEFAULT_CHUNK_SIZE;
+
+/// Put between the columns of a joined search
+const JOIN_SEPARATOR: &str = " ";
+
+/// What each line is searched in
+#[derive(Debug, Clone, Copy)]
+pub enum SearchTarget<'a> {
+    /// Each parsed column on its own, except these
+    Columns(&'a [u8]),
+    /// The whole line, without parsing
+    Line,
+    /// These parsed columns, joined with JOIN_SEPARATOR
+    Joined(&'a [u8]),
+}
+
+/// How the matches of a search are reported
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOutput<'a> {
+    /// Names of the columns, each match then carries the name of its column
+    pub column_names: Option<&'a [String]>,
+    /// Send matches in SearchResultsPartial messages while searching
+    pub stream: bool,
+    /// Session the search runs in, its SearchProgress and SearchResultsPartial messages
+    /// are tagged with it
+    pub file_id: &'a Option<String>,
+    /// Count start_index and end_index in characters instead of bytes
+    pub char_offsets: bool,
+}
+
+/// Characters with a meaning in a regex, a pattern without any is searched as plain text
+const REGEX_META_CHARACTERS: &str = "\\.+*?()|[]{}^$";
+
+/// How a search pattern is matched
+pub enum SearchMatcher {
+    Regex(regex::Regex),
+    /// The pattern is plain text, found with memchr's substring search
+    Literal(Box<memmem::Finder<'static>>),
+}
+
+impl SearchMatcher {
+    /// Whether the pattern occurs anywhere in text
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Self::Regex(regex) => regex.is_match(text),
+            Self::Literal(finder) => finder.find(text.as_bytes()).is_some(),
+        }
+    }
+}
+
+/// Compiles the user's search pattern, applying the search options
+/// A pattern without regex metacharacters and options changing its meaning skips the
+/// regex engine, its matches are the same
+/// size_limit and dfa_size_limit override the regex crate defaults (in bytes)
+pub fn build_search_matcher(
+    pattern: &str,
+    case_insensitive: bool,
+    whole_word: bool,
+    dot_matches_newline: bool,
+    ignore_whitespace: bool,
+    size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+) -> Result<SearchMatcher, String> {
+    let literal = !pattern.is_empty()
+        && !case_insensitive
+        && !whole_word
+        && !ignore_whitespace
+        && !pattern.contains(|c| REGEX_META_CHARACTERS.contains(c));
+    if literal {
+        return Ok(SearchMatcher::Literal(Box::new(
+            memmem::Finder::new(pattern).into_owned(),
+        )));
+    }
+
+    build_search_regex(
+        pattern,
+        case_insensitive,
+        whole_word,
+        dot_matches_newline,
+        ignore_whitespace,
+        size_limit,
+        dfa_size_limit,
+    )
+    .map(SearchMatcher::Regex)
+}
+
+/// Compiles the user's search pattern as a regex, applying the search options
+fn build_search_regex(
+    pattern: &str,
+    case_insensitive: bool,
+    whole_word: bool,
+    dot_matches_newline: bool,
+    ignore_whitespace: bool,
+    size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+) -> Result<regex::Regex, String> {
+    let pattern = match (whole_word, ignore_whitespace) {
+        // In verbose mode a trailing # comment would swallow the closing group
+        (true, true) => format!("\\b(?:{}\n)\\b", pattern),
+        (true, false) => format!(r"\b(?:{})\b", pattern),
+        (false, _) => pattern.to_string(),
+    };
+
+    let mut builder = regex::RegexBuilder::new(&pattern);
+    builder
+        .case_insensitive(case_insensitive)
+        .dot_matches_new_line(dot_matches_newline)
+        .ignore_whitespace(ignore_whitespace);
+    if let Some(limit) = size_limit {
+        builder.size_limit(limit);
+    }
+    if let Some(limit) = dfa_size_limit {
+        builder.dfa_size_limit(limit);
+    }
+
+    builder.build().map_err(|e| match e {
+        regex::Error::CompiledTooBig(limit) => format!(
+            "Regex pattern is too large: compiled size exceeds the limit of {} bytes. Simplify the pattern or raise size_limit.",
+            limit
+        ),
+        e => format!("Invalid regex pattern: {}", e),
+    })
+}
+
+/// Everything search_chunk needs besides the range of lines
+struct SearchContext<'a> {
+    processor: &'a FileProcessor,
+    line_parser: &'a Option<LineParser>,
+    matcher: &'a SearchMatcher,
+    nbr_columns: Option<u8>,
+    target: SearchTarget<'a>,
+    column_names: Option<&'a [String]>,
+    char_offsets: bool,
+    column_total: usize, // Columns of a parsed line
+    strip_ansi: bool,    // Whole lines are searched as GetChunk returns them, not as written
+}
+
+impl SearchContext<'_> {
+    /// start_index and end_index of a match in text, in bytes or in characters
+    fn indices(&self, text: &str, mat: &Range<usize>) -> (u64, u64) {
+        if !self.char_offsets {
+            return (mat.start as u64, mat.end as u64);
+        }
+
+        let start = text[..mat.start].chars().count();
+        let end = start + text[mat.clone()].chars().count();
+        (start as u64, end as u64)
+    }
+}
+
 /// Searches through all lines in the file for matches
+/// `target` tells whether the parsed columns, the original lines or a join of columns are searched
+/// When `output.column_names` is set, each match carries the name of its column
+/// Matches are sorted by line, column and start, and the search stops at the first MAX_RESULTS
+/// When `output.stream` is set, matches are sent in SearchResultsPartial messages after each
+/// batch of chunks (in file order), and the final SearchResults only has the totals
+/// Each parallel task searches `chunk_size` lines
 pub fn search(
     processor: &FileProcessor,
-    regex_pattern_parser: &Option<regex::Regex>,
-    regex_pattern_search: &regex::Regex,
+    line_parser: &Option<LineParser>,
+    matcher: &SearchMatcher,
     nbr_columns: Option<u8>,
+    target: SearchTarget,
+    output: SearchOutput,
+    chunk_size: usize,
 ) -> Response {
-    const CHUNK_SIZE: usize = 10_000; // Lines per chunk
+    let SearchOutput {
+        column_names,
+        stream,
+        file_id,
+        char_offsets,
+    } = output;
+
     const MAX_RESULTS: usize = 1_000; // Stop after finding 1000 matches
 
+    let started_at = Instant::now();
     let line_count = processor.index.len();
-    let total_chunks = line_count.div_ceil(CHUNK_SIZE);
-    let mut search_complete = true;
+
+    // Without parsing, every line is a single column, searched whole like GetChunk returns it
+    // (without ANSI escape sequences with strip_ansi); a raw search sees the line as written
+    let strip_ansi = matches!(target, SearchTarget::Columns([]))
+        && line_parser.is_none()
+        && processor.strip_ansi();
+    let target = match target {
+        SearchTarget::Columns([]) if line_parser.is_none() => SearchTarget::Line,
+        target => target,
+    };
+    let checked = match target {
+        SearchTarget::Joined(join) => check_join_columns(processor, line_parser, nbr_columns, join),
+        SearchTarget::Columns([]) | SearchTarget::Line => Ok(()),
+        SearchTarget::Columns(excluded) => check_columns(
+            processor,
+            line_parser,
+            nbr_columns,
+            excluded,
+            "exclude_columns",
+        ),
+    };
+    if let Err(err) = checked {
+        return err.into();
+    }
+    let mode = match target {
+        SearchTarget::Columns(_) => SearchMode::Parsed,
+        SearchTarget::Line => SearchMode::Raw,
+        SearchTarget::Joined(_) => SearchMode::Joined,
+    };
+
+    // Nothing to search in an empty file
+    if line_count == 0 {
+        return Response::SearchResults {
+            matches: Vec::new(),
+            total_matches: 0,
+            search_complete: true,
+            duration_ms: started_at.elapsed().as_millis() as u64,
+            lines_scanned: 0,
+            per_column_counts: Vec::new(),
+            mode,
+        };
+    }
+
+    let total_chunks = line_count.div_ceil(chunk_size);
 
     // Progress tracking
     let completed_chunks = Arc::new(AtomicUsize::new(0));
     let last_reported_percent = Arc::new(AtomicU8::new(0));
+    let show_progress = line_count >= QUIET_SEARCH_LINES;
+
+    // Lines actually visited; chunks skipped by the early stop are not counted
+    let mut lines_scanned = 0;
+    // Set when a match isn't reported: past MAX_RESULTS or a line's cap, or in a chunk that
+    // couldn't be read
+    let dropped = AtomicBool::new(false);
+
+    // Hits per column across all scanned lines (raw and joined searches, and unparsed
+    // lines, use column 0)
+    let column_total = match target {
+        SearchTarget::Columns(_) => utils::column_count(processor, line_parser, nbr_columns),
+        SearchTarget::Line | SearchTarget::Joined(_) => 1,
+    };
+    let column_counts: Vec<AtomicU32> = (0..column_total.max(1))
+        .map(|_| AtomicU32::new(0))
+        .collect();
+
+    let context = SearchContext {
+        processor,
+        line_parser,
+        matcher,
+        nbr_columns,
+        target,
+        column_names,
+        char_offsets,
+        column_total,
+        strip_ansi,
+    };
 
     // Report 0% at start
-    println!("{{\"SearchProgress\":{{\"percent\":0}}}}");
-
-    // Parallel search across chunks
-    let matches: Vec<SearchMatch> = (0..line_count)
-        .into_par_iter()
-        .step_by(CHUNK_SIZE)
-        .flat_map(|chunk_start| {
-            let result = search_chunk(
-                processor,
-                regex_pattern_parser,
-                regex_pattern_search,
-                nbr_columns,
-                chunk_start,
-                CHUNK_SIZE.min(line_count - chunk_start),
-            )
-            .unwrap_or_else(|e| {
-                // Log error but continue searching other chunks
-                let response = Response::Info {
-                    message: format!(
-                        "Failed to search chunk starting at line {}: {}",
-                        chunk_start, e
-                    ),
+    if show_progress {
+        print_progress(file_id, 0);
+    }
+
+    // Chunks are searched in parallel, batch by batch, so the search can stop once
+    // MAX_RESULTS matches are found and still keep the first ones in the file
+    // One chunk per thread, so a search that stops early doesn't read much past its matches
+    let chunks_per_batch = rayon::current_num_threads();
+    let mut matches: Vec<SearchMatch> = Vec::new();
+    for batch_start in (0..total_chunks).step_by(chunks_per_batch) {
+        let batch_end = (batch_start + chunks_per_batch).min(total_chunks);
+
+        let batch: Vec<Vec<SearchMatch>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|chunk| {
+                let chunk_start = chunk * chunk_size;
+                let count = chunk_size.min(line_count - chunk_start);
+
+                let result = match search_chunk(&context, chunk_start, count) {
+                    Ok((result, capped)) => {
+                        if capped {
+                            dropped.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    }
+                    Err(e) => {
+                        // Log error but continue searching other chunks
+                        let response = Response::Info {
+                            message: format!(
+                                "Failed to search chunk starting at line {}: {}",
+                                chunk_start, e
+                            ),
+                        };
+                        eprintln!("{}", serde_json::to_string(&response).unwrap());
+                        dropped.store(true, Ordering::Relaxed);
+
+                        Vec::new() // Return empty vec for failed chunk
+                    }
                 };
-                eprintln!("{}", serde_json::to_string(&response).unwrap());
 
-                Vec::new() // Return empty vec for failed chunk
-            });
+                for m in &result {
+                    if let Some(counter) = column_counts.get(m.column as usize) {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
 
-            // Update progress after chunk completes
-            let finished = completed_chunks.fetch_add(1, Ordering::Relaxed) + 1;
-            report_progress(finished, total_chunks, &last_reported_percent);
+                // Update progress after chunk completes
+                let finished = completed_chunks.fetch_add(1, Ordering::Relaxed) + 1;
+                if show_progress {
+                    report_progress(file_id, finished, total_chunks, &last_reported_percent);
+                }
 
-            result
-        })
-        .take_any(MAX_RESULTS)
-        .collect();
+                result
+            })
+            .collect();
+
+        lines_scanned += (batch_end * chunk_size).min(line_count) - batch_start * chunk_size;
+
+        let mut found: Vec<SearchMatch> = batch.into_iter().flatten().collect();
+        found.sort_by_key(|m| (m.line_number, m.column, m.start_index));
+        let room = MAX_RESULTS - matches.len();
+        if found.len() > room {
+            dropped.store(true, Ordering::Relaxed);
+        }
+        found.truncate(room);
+
+        if stream && !found.is_empty() {
+            print_partial_results(file_id, &found);
+        }
+        matches.extend(found);
+
+        if matches.len() >= MAX_RESULTS {
+            break;
+        }
+    }
 
     // Report 100% at the end
-    println!("{{\"SearchProgress\":{{\"percent\":100}}}}");
+    if show_progress {
+        print_progress(file_id, 100);
+    }
 
     let nbr_matches = matches.len();
-    if nbr_matches >= MAX_RESULTS {
-        search_complete = false;
+    let search_complete = !dropped.into_inner() && lines_scanned == line_count;
+
+    // Streamed matches were already sent, only the totals are left
+    if stream {
+        matches.clear();
     }
 
     Response::SearchResults {
         matches,
         total_matches: nbr_matches as u32,
         search_complete,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+        lines_scanned: lines_scanned as u64,
+        per_column_counts: column_counts
+            .iter()
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .collect(),
+        mode,
     }
 }
 
+/// Matches of the lines in the chunk, and whether a line had more than MAX_MATCHES_PER_LINE
 fn search_chunk(
-    processor: &FileProcessor,
-    regex_pattern_parser: &Option<regex::Regex>,
-    regex_pattern_search: &regex::Regex,
-    nbr_columns: Option<u8>,
+    context: &SearchContext,
     start_line: usize,
     count: usize,
-) -> Result<Vec<SearchMatch>, String> {
+) -> Result<(Vec<SearchMatch>, bool), String> {
     // Read lines - return error if fails
-    let lines = processor.read_lines_range(start_line as u64, (start_line + count) as u64)?;
+    let lines = context
+        .processor
+        .scan_lines_range(start_line as u64, (start_line + count - 1) as u64)?;
 
     let mut matches: Vec<SearchMatch> = Vec::new();
+    let mut capped = false;
+
+    if let SearchTarget::Joined(join) = context.target {
+        return Ok(search_joined(context, join, &lines, start_line));
+    }
+
+    // Search each line directly instead of wrapping it in a Vec first
+    if let SearchTarget::Line = context.target {
+        let name = context
+            .column_names
+            .map(|_| log_format_patterns::RAW_COLUMN_NAME.to_string());
+
+        for (line_idx, line) in lines.iter().enumerate() {
+            let line = utils::clean_line(line, context.strip_ansi);
+            let (found, line_capped) = find_matches(context.matcher, &line, MAX_MATCHES_PER_LINE);
+            capped |= line_capped;
+            for mat in found {
+                let (start_index, end_index) = context.indices(&line, &mat);
+                matches.push(SearchMatch {
+                    line_number: (start_line + line_idx) as u64,
+                    column: 0,
+                    start_index,
+                    end_index,
+                    column_name: name.clone(),
+                });
+            }
+        }
+
+        return Ok((matches, capped));
+    }
 
     let parsed_lines = utils::parse_data(
-        regex_pattern_parser,
-        nbr_columns,
+        context.line_parser,
+        context.nbr_columns,
+        context.processor.strip_ansi(),
         &lines,
         start_line as u64,
         false, // Don't show parsing errors during search
     );
 
+    let excluded = match context.target {
+        SearchTarget::Columns(excluded) => excluded,
+        SearchTarget::Line | SearchTarget::Joined(_) => &[],
+    };
+
     // Search within each parsed line's columns
     for (line_idx, columns) in parsed_lines.iter().enumerate() {
+        let line_first_match = matches.len();
+        // Lines that didn't parse are a single raw column, searched whole
+        let parsed = columns.len() == context.column_total;
         for (col_idx, column) in columns.iter().enumerate() {
-            // Find all matches in this column
-            for mat in regex_pattern_search.find_iter(column) {
+            // Columns are numbered with a u8 (nbr_columns, excluded columns), later ones can't be reported
+            let Ok(column_number) = u8::try_from(col_idx) else {
+                break;
+            };
+            if parsed && excluded.contains(&column_number) {
+                continue;
+            }
+
+            // Find the matches in this column, within what's left of the line's cap
+            let room = MAX_MATCHES_PER_LINE - (matches.len() - line_first_match);
+            let (found, column_capped) = find_matches(context.matcher, column, room);
+            capped |= column_capped;
+            for mat in found {
+                let (start_index, end_index) = context.indices(column, &mat);
                 matches.push(SearchMatch {
-                    line_number: (start_line + line_idx) as u32,
-                    column: col_idx as u8,
-                    start_index: mat.start() as u16,
-                    end_index: mat.end() as u16,
+                    line_number: (start_line + line_idx) as u64,
+                    column: column_number,
+                    start_index,
+                    end_index,
+                    column_name: context
+                        .column_names
+                        .map(|names| column_name(names, columns.len(), col_idx)),
                 });
             }
         }
     }
 
-    Ok(matches)
+    Ok((matches, capped))
+}
+
+/// Searches the join of the given columns in each line
+/// Lines that don't parse are searched whole, and a column missing from a line joins as empty
+/// Also tells whether a line had more than MAX_MATCHES_PER_LINE
+fn search_joined(
+    context: &SearchContext,
+    join: &[u8],
+    lines: &[String],
+    start_line: usize,
+) -> (Vec<SearchMatch>, bool) {
+    let Some(parser) = context.line_parser else {
+        return (Vec::new(), false);
+    };
+    let joined_name = context.column_names.map(|names| {
+        join.iter()
+            .map(|&column| names.get(column as usize).map_or("", String::as_str))
+            .collect::<Vec<&str>>()
+            .join("+")
+    });
+
+    let mut matches = Vec::new();
+    let mut capped = false;
+    for (line_idx, line) in lines.iter().enumerate() {
+        let line = utils::clean_line(line, context.processor.strip_ansi());
+        let (text, name) = match utils::parse_line_nullable(parser, context.nbr_columns, &line) {
+            Some(columns) => {
+                let joined = join
+                    .iter()
+                    .map(|&column| {
+                        columns
+                            .get(column as usize)
+                            .and_then(Option::as_deref)
+                            .unwrap_or("")
+                    })
+                    .collect::<Vec<&str>>()
+                    .join(JOIN_SEPARATOR);
+                (joined, joined_name.clone())
+            }
+            None => (
+                line.into_owned(),
+                context
+                    .column_names
+                    .map(|_| log_format_patterns::RAW_COLUMN_NAME.to_string()),
+            ),
+        };
+
+        let (found, line_capped) = find_matches(context.matcher, &text, MAX_MATCHES_PER_LINE);
+        capped |= line_capped;
+        for mat in found {
+            let (start_index, end_index) = context.indices(&text, &mat);
+            matches.push(SearchMatch {
+                line_number: (start_line + line_idx) as u64,
+                column: 0,
+                start_index,
+                end_index,
+                column_name: name.clone(),
+            });
+        }
+    }
+
+    (matches, capped)
+}
+
+/// A joined search needs parsed lines and columns that exist
+fn check_join_columns(
+    processor: &FileProcessor,
+    line_parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+    join: &[u8],
+) -> Result<(), CodedError> {
+    check_columns(processor, line_parser, nbr_columns, join, "join_columns")?;
+    if join.is_empty() {
+        return Err(CodedError::new(
+            ErrorCode::InvalidArgument,
+            "join_columns is empty",
+        ));
+    }
+    Ok(())
+}
+
+/// Columns named by a search option (`option`) need parsed lines and must exist
+fn check_columns(
+    processor: &FileProcessor,
+    line_parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+    columns: &[u8],
+    option: &str,
+) -> Result<(), CodedError> {
+    if line_parser.is_none() {
+        return Err(CodedError::new(
+            ErrorCode::InvalidArgument,
+            format!("{} needs parsed lines, use ParseFile first", option),
+        ));
+    }
+
+    let column_total = utils::column_count(processor, line_parser, nbr_columns);
+    match columns
+        .iter()
+        .find(|&&column| column as usize >= column_total)
+    {
+        Some(column) => Err(CodedError::new(
+            ErrorCode::OutOfBounds,
+            format!(
+                "column ({}) out of bounds (lines have {} columns)",
+                column, column_total
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Byte ranges of up to `limit` matches of the search pattern in text, and whether more
+/// were left out
+/// Zero-width matches (e.g. `a*` between two letters) are dropped, but a text with
+/// nothing else (e.g. `^$` on an empty line) keeps its first one so it still shows up
+fn find_matches(matcher: &SearchMatcher, text: &str, limit: usize) -> (Vec<Range<usize>>, bool) {
+    let regex = match matcher {
+        SearchMatcher::Regex(regex) => regex,
+        // Occurrences don't overlap, like regex matches
+        SearchMatcher::Literal(finder) => {
+            let len = finder.needle().len();
+            let mut found: Vec<Range<usize>> = finder
+                .find_iter(text.as_bytes())
+                .take(limit.saturating_add(1))
+                .map(|start| start..start + len)
+                .collect();
+            let capped = found.len() > limit;
+            found.truncate(limit);
+            return (found, capped);
+        }
+    };
+
+    let mut found = Vec::new();
+    let mut first_empty = None;
+
+    for mat in regex.find_iter(text) {
+        if mat.is_empty() {
+            first_empty.get_or_insert(mat.range());
+        } else if found.len() == limit {
+            return (found, true);
+        } else {
+            found.push(mat.range());
+        }
+    }
+
+    if found.is_empty() {
+        if limit == 0 {
+            return (found, first_empty.is_some());
+        }
+        found.extend(first_empty);
+    }
+    (found, false)
 }
 
-/// Reports progress milestones (10%, 20%, 30%, etc.) to stderr as JSON
-fn report_progress(completed: usize, total: usize, last_reported: &Arc<AtomicU8>) {
-    let percent = ((completed * 100) / total) as u8;
+/// Lines that failed to parse only have the raw line in column 0
+fn column_name(names: &[String], nbr_columns: usize, column: usize) -> String {
+    if nbr_columns != names.len() {
+        return log_format_patterns::RAW_COLUMN_NAME.to_string();
+    }
+    names[column].clone()
+}
+
+/// Reports progress milestones (10%, 20%, 30%, etc.) of a search in session `file_id`
+pub(super) fn report_progress(
+    file_id: &Option<String>,
+    completed: usize,
+    total: usize,
+    last_reported: &Arc<AtomicU8>,
+) {
+    if total == 0 {
+        return;
+    }
+
+    // Widened so the product can't overflow, whatever the number of chunks
+    let percent = (completed as u128 * 100 / total as u128) as u8;
     let milestone = (percent / 10) * 10; // Snap to 0, 10, 20, 30, ...
     let last = last_reported.load(Ordering::Relaxed);
 
@@ -133,7 +656,294 @@ fn report_progress(completed: usize, total: usize, last_reported: &Arc<AtomicU8>
             .compare_exchange(last, milestone, Ordering::SeqCst, Ordering::Relaxed)
             .is_ok()
         {
-            println!("{{\"SearchProgress\":{{\"percent\":{}}}}}", milestone);
+            print_progress(file_id, milestone);
+        }
+    }
+}
+
+/// Prints a SearchResultsPartial response of session `file_id` to stdout
+fn print_partial_results(file_id: &Option<String>, matches: &[SearchMatch]) {
+    let response = Response::SearchResultsPartial {
+        matches: matches.to_vec(),
+    };
+    services::output::send_to(file_id, response);
+}
+
+/// Prints a SearchProgress response of session `file_id` to stdout
+pub(super) fn print_progress(file_id: &Option<String>, percent: u8) {
+    let response = Response::SearchProgress { percent };
+    services::output::send_to(file_id, response);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_files::TempFile;
+
+    /// Searches the whole lines of a file, the way Search does without a format
+    fn search_lines(file: &TempFile, pattern: &str) -> (Vec<SearchMatch>, bool) {
+        search_with(file, &None, pattern, SearchTarget::Line)
+    }
+
+    fn search_with(
+        file: &TempFile,
+        line_parser: &Option<LineParser>,
+        pattern: &str,
+        target: SearchTarget,
+    ) -> (Vec<SearchMatch>, bool) {
+        let processor = file.open("utf-8");
+        let matcher =
+            build_search_matcher(pattern, false, false, false, false, None, None).unwrap();
+        let output = SearchOutput {
+            column_names: None,
+            stream: false,
+            file_id: &None,
+            char_offsets: false,
+        };
+
+        match search(
+            &processor,
+            line_parser,
+            &matcher,
+            None,
+            target,
+            output,
+            utils::DEFAULT_CHUNK_SIZE,
+        ) {
+            Response::SearchResults {
+                matches,
+                search_complete,
+                ..
+            } => (matches, search_complete),
+            response => panic!("unexpected response: {:?}", response),
         }
     }
+
+    /// Line, start and end of each match of a whole-line search with `matcher`
+    fn match_positions(
+        processor: &FileProcessor,
+        matcher: &SearchMatcher,
+        char_offsets: bool,
+    ) -> Vec<(u64, u64, u64)> {
+        let output = SearchOutput {
+            column_names: None,
+            stream: false,
+            file_id: &None,
+            char_offsets,
+        };
+        match search(
+            processor,
+            &None,
+            matcher,
+            None,
+            SearchTarget::Line,
+            output,
+            utils::DEFAULT_CHUNK_SIZE,
+        ) {
+            Response::SearchResults { matches, .. } => matches
+                .iter()
+                .map(|m| (m.line_number, m.start_index, m.end_index))
+                .collect(),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    /// The same pattern through the regex engine
+    fn regex_matcher(pattern: &str) -> SearchMatcher {
+        SearchMatcher::Regex(regex::Regex::new(&regex::escape(pattern)).unwrap())
+    }
+
+    #[test]
+    fn literal_matches_like_regex() {
+        let file = TempFile::new("héllo wörld héllo\nééé ééé\naaaa\n日本語 日本\n".as_bytes());
+        let processor = file.open("utf-8");
+
+        for pattern in ["héllo", "é", "éé", "aa", "日本", "wörld héllo", "missing"] {
+            let literal =
+                build_search_matcher(pattern, false, false, false, false, None, None).unwrap();
+            assert!(matches!(literal, SearchMatcher::Literal(_)));
+
+            for char_offsets in [false, true] {
+                assert_eq!(
+                    match_positions(&processor, &literal, char_offsets),
+                    match_positions(&processor, &regex_matcher(pattern), char_offsets),
+                    "pattern {:?}, char_offsets {}",
+                    pattern,
+                    char_offsets
+                );
+            }
+        }
+    }
+
+    /// Time of a plain text search through memchr and through the regex engine, on 1M lines
+    /// cargo test --release literal_search_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn literal_search_benchmark() {
+        const LINES: usize = 1_000_000;
+        const PATTERN: &str = "connection reset";
+
+        let text: String = (0..LINES)
+            .map(|line| match line % 5_000 {
+                0 => format!(
+                    "2024-01-02 12:00:{:02} WARN {} by peer\n",
+                    line % 60,
+                    PATTERN
+                ),
+                _ => format!(
+                    "2024-01-02 12:00:{:02} INFO request {} served in 3ms\n",
+                    line % 60,
+                    line
+                ),
+            })
+            .collect();
+        let file = TempFile::new(text.as_bytes());
+        let processor = file.open("utf-8");
+
+        let literal =
+            build_search_matcher(PATTERN, false, false, false, false, None, None).unwrap();
+        let regex = regex_matcher(PATTERN);
+        // The first search reads the file into the cache, only the matching is timed
+        match_positions(&processor, &literal, false);
+
+        let started_at = Instant::now();
+        let literal_found = match_positions(&processor, &literal, false);
+        let literal_time = started_at.elapsed();
+
+        let started_at = Instant::now();
+        let regex_found = match_positions(&processor, &regex, false);
+        let regex_time = started_at.elapsed();
+
+        assert_eq!(literal_found, regex_found);
+        println!(
+            "{} lines, {} matches: literal {:?}, regex {:?}",
+            LINES,
+            literal_found.len(),
+            literal_time,
+            regex_time
+        );
+    }
+
+    #[test]
+    fn search_empty_file() {
+        let file = TempFile::new(b"");
+        let (matches, complete) = search_lines(&file, "a");
+
+        assert!(matches.is_empty());
+        assert!(complete);
+    }
+
+    #[test]
+    fn search_single_blank_line() {
+        let file = TempFile::new(b"\n");
+
+        let (matches, complete) = search_lines(&file, "a");
+        assert!(matches.is_empty());
+        assert!(complete);
+
+        let (matches, complete) = search_lines(&file, "^$");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 0);
+        assert!(complete);
+    }
+
+    #[test]
+    fn search_skips_empty_matches() {
+        let file = TempFile::new(b"baaab\nbbb\n\n");
+        let (matches, complete) = search_lines(&file, "a*");
+        assert!(complete);
+
+        // Only the run of a's on the first line, not the empty matches around it
+        let found: Vec<(u64, u64, u64)> = matches
+            .iter()
+            .map(|m| (m.line_number, m.start_index, m.end_index))
+            .collect();
+        assert_eq!(found, [(0, 1, 4), (1, 0, 0), (2, 0, 0)]);
+    }
+
+    #[test]
+    fn search_complete_unless_matches_are_dropped() {
+        // Exactly 1000 matches, the most a search reports, are all reported
+        let file = TempFile::new("a\n".repeat(1_000).as_bytes());
+        let (matches, complete) = search_lines(&file, "a");
+        assert_eq!(matches.len(), 1_000);
+        assert!(complete);
+
+        let file = TempFile::new("a\n".repeat(1_001).as_bytes());
+        let (matches, complete) = search_lines(&file, "a");
+        assert_eq!(matches.len(), 1_000);
+        assert!(!complete);
+
+        // A line with more than MAX_MATCHES_PER_LINE matches loses some
+        let file = TempFile::new(format!("{}\n", "a".repeat(MAX_MATCHES_PER_LINE + 1)).as_bytes());
+        let (matches, complete) = search_lines(&file, "a");
+        assert_eq!(matches.len(), MAX_MATCHES_PER_LINE);
+        assert!(!complete);
+    }
+
+    #[test]
+    fn search_empty_line_pattern() {
+        let file = TempFile::new(b"a\n\nb\n");
+        let (matches, _) = search_lines(&file, "^$");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 1);
+        assert_eq!(matches[0].start_index, matches[0].end_index);
+    }
+
+    #[test]
+    fn search_unparsed_lines_without_ansi() {
+        let file = TempFile::new(b"\x1b[31mERROR\x1b[0m disk full\n");
+        let mut processor = file.open("utf-8");
+        processor.set_strip_ansi(true);
+        let matcher = build_search_matcher("disk", false, false, false, false, None, None).unwrap();
+        let output = SearchOutput {
+            column_names: None,
+            stream: false,
+            file_id: &None,
+            char_offsets: false,
+        };
+        let offsets = |target| match search(
+            &processor,
+            &None,
+            &matcher,
+            None,
+            target,
+            output,
+            utils::DEFAULT_CHUNK_SIZE,
+        ) {
+            Response::SearchResults { matches, .. } => matches
+                .iter()
+                .map(|m| (m.start_index, m.end_index))
+                .collect::<Vec<_>>(),
+            response => panic!("unexpected response: {:?}", response),
+        };
+
+        // Offsets into the line GetChunk returns, without the colors
+        assert_eq!(offsets(SearchTarget::Columns(&[])), [(6, 10)]);
+        // A raw search sees the line as written
+        assert_eq!(offsets(SearchTarget::Line), [(15, 19)]);
+    }
+
+    #[test]
+    fn search_anchors_to_columns_or_line() {
+        let file = TempFile::new(b"12:00 ERROR disk full\nERROR at start\n");
+        let parser = Some(LineParser::Regex {
+            regex: regex::Regex::new(r"(\S+) (\S+) (.*)").unwrap(),
+            trim: false,
+        });
+
+        // With a format, ^ anchors to the start of each column, mid-line ones included
+        let (matches, _) = search_with(&file, &parser, "^ERROR", SearchTarget::Columns(&[]));
+        let found: Vec<(u64, u8)> = matches.iter().map(|m| (m.line_number, m.column)).collect();
+        assert_eq!(found, [(0, 1), (1, 0)]);
+
+        // On the raw line (raw or anchor_to_line), only the line starting with ERROR matches
+        let (matches, _) = search_with(&file, &parser, "^ERROR", SearchTarget::Line);
+        let found: Vec<(u64, u64)> = matches
+            .iter()
+            .map(|m| (m.line_number, m.start_index))
+            .collect();
+        assert_eq!(found, [(1, 0)]);
+    }
 }
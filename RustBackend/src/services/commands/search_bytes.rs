@@ -0,0 +1,170 @@
+use memchr::memmem;
+use rayon::prelude::*;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+use crate::{
+    services::{
+        FileProcessor,
+        commands::search::{QUIET_SEARCH_LINES, print_progress, report_progress},
+    },
+    types::{ByteMatch, Response},
+};
+
+/// Parses a hex string like "DE AD BE EF" or "deadbeef" into bytes
+/// Whitespace between bytes is ignored
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if digits.is_empty() {
+        return Err(String::from("Hex pattern is empty"));
+    }
+
+    if let Some(invalid) = digits.iter().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "Invalid hex pattern: '{}' is not a hex digit",
+            invalid
+        ));
+    }
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!(
+            "Invalid hex pattern: {} digits given, bytes need 2 digits each",
+            digits.len()
+        ));
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| {
+            let high = pair[0].to_digit(16).unwrap() as u8;
+            let low = pair[1].to_digit(16).unwrap() as u8;
+            (high << 4) | low
+        })
+        .collect())
+}
+
+/// Searches the undecoded bytes of the file for a byte sequence
+/// Matches are reported with their byte offset in the file and in their line
+/// Chunks are searched in parallel, batch by batch like Search, so the first MAX_RESULTS
+/// matches in the file are kept, sorted by byte_offset
+/// Its SearchProgress messages are tagged with the session `file_id`
+pub fn search_bytes(
+    processor: &FileProcessor,
+    needle: &[u8],
+    file_id: &Option<String>,
+) -> Response {
+    const CHUNK_SIZE: usize = 10_000; // Lines per chunk
+    const MAX_RESULTS: usize = 1_000; // Stop after finding 1000 matches
+
+    let started_at = Instant::now();
+    let line_count = processor.index.len();
+
+    let total_chunks = line_count.div_ceil(CHUNK_SIZE);
+    let completed_chunks = AtomicUsize::new(0);
+    let last_reported_percent = Arc::new(AtomicU8::new(0));
+    let finder = memmem::Finder::new(needle);
+    let show_progress = line_count >= QUIET_SEARCH_LINES;
+
+    if show_progress {
+        print_progress(file_id, 0);
+    }
+
+    // One chunk per thread, so a search that stops early doesn't read much past its matches
+    let chunks_per_batch = rayon::current_num_threads();
+    let mut matches: Vec<ByteMatch> = Vec::new();
+    let mut dropped_matches = false;
+    let mut lines_searched = 0;
+    for batch_start in (0..total_chunks).step_by(chunks_per_batch) {
+        let batch_end = (batch_start + chunks_per_batch).min(total_chunks);
+
+        let batch: Vec<Vec<ByteMatch>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|chunk| {
+                let chunk_start = chunk * CHUNK_SIZE;
+                let chunk_end = (chunk_start + CHUNK_SIZE).min(line_count) - 1;
+
+                let result = search_chunk(processor, &finder, chunk_start as u64, chunk_end as u64)
+                    .unwrap_or_else(|e| {
+                        // Log error but continue searching other chunks
+                        let response = Response::Info {
+                            message: format!(
+                                "Failed to search chunk starting at line {}: {}",
+                                chunk_start, e
+                            ),
+                        };
+                        eprintln!("{}", serde_json::to_string(&response).unwrap());
+
+                        Vec::new()
+                    });
+
+                let finished = completed_chunks.fetch_add(1, Ordering::Relaxed) + 1;
+                if show_progress {
+                    report_progress(file_id, finished, total_chunks, &last_reported_percent);
+                }
+
+                result
+            })
+            .collect();
+
+        lines_searched = (batch_end * CHUNK_SIZE).min(line_count);
+
+        // Chunks come back in file order and each one's matches are in order
+        let found: Vec<ByteMatch> = batch.into_iter().flatten().collect();
+        let room = MAX_RESULTS - matches.len();
+        dropped_matches |= found.len() > room;
+        matches.extend(found.into_iter().take(room));
+
+        if matches.len() >= MAX_RESULTS {
+            break;
+        }
+    }
+
+    if show_progress {
+        print_progress(file_id, 100);
+    }
+
+    Response::ByteSearchResults {
+        total_matches: matches.len() as u32,
+        search_complete: !dropped_matches && lines_searched == line_count,
+        matches,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
+
+/// Finds the matches starting in the lines from start_line to end_line
+/// The bytes after end_line are read too, as far as a match starting in the chunk can reach,
+/// so a sequence spanning two chunks is found (once, by the chunk it starts in)
+fn search_chunk(
+    processor: &FileProcessor,
+    finder: &memmem::Finder,
+    start_line: u64,
+    end_line: u64,
+) -> Result<Vec<ByteMatch>, String> {
+    let chunk_bytes = processor.line_end_byte(end_line) - processor.line_start_byte(start_line);
+    let overlap = finder.needle().len() as u64 - 1;
+    let (bytes, start_byte, _) =
+        processor.read_raw_range(start_line, u64::MAX, chunk_bytes + overlap)?;
+
+    let matches = finder
+        .find_iter(&bytes)
+        .take_while(|&position| (position as u64) < chunk_bytes)
+        .filter_map(|position| {
+            let byte_offset = start_byte + position as u64;
+            let line_number = processor.line_at_byte(byte_offset)?;
+
+            Some(ByteMatch {
+                line_number,
+                byte_offset,
+                line_offset: byte_offset - processor.line_start_byte(line_number),
+            })
+        })
+        .collect();
+
+    Ok(matches)
+}
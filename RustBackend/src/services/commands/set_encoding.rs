@@ -0,0 +1,15 @@
+use crate::{services::FileProcessor, types::Response};
+
+pub fn set_encoding(processor: &mut FileProcessor, label: &str) -> Response {
+    if let Err(err) = processor.set_encoding(label) {
+        return Response::Error {
+            message: format!("Couldn't change the encoding to {}: {}", label, err.message),
+            code: Some(err.code),
+        };
+    }
+
+    Response::EncodingChanged {
+        encoding: processor.encoding_label.clone(),
+        line_count: processor.index.len() as u64,
+    }
+}
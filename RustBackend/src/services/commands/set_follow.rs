@@ -0,0 +1,40 @@
+use crate::{
+    services::{Session, commands},
+    types::{ErrorCode, Response},
+};
+
+/// Start or stop following the opened file
+/// Without a watcher no thread runs and changes to the file aren't reported,
+/// starting it again reports what changed in the meantime
+pub fn set_follow(session: &mut Session, file_id: Option<String>, enabled: bool) -> Response {
+    let compressed = match session.file_state.lock() {
+        Ok(guard) => match guard.as_ref() {
+            Some(fs) => fs.processor.compression.is_some(),
+            None => {
+                return Response::Error {
+                    message: "use OpenFile command before following the file.".to_string(),
+                    code: Some(ErrorCode::NotOpen),
+                };
+            }
+        },
+        Err(_poisoned) => {
+            return Response::Error {
+                message: "FileState mutex is poisoned.".to_string(),
+                code: Some(ErrorCode::Internal),
+            };
+        }
+    };
+
+    if !enabled {
+        session.stop_watcher();
+    } else if compressed {
+        return Response::Error {
+            message: String::from("A decompressed copy never changes, it can't be followed"),
+            code: Some(ErrorCode::InvalidArgument),
+        };
+    } else if session.watcher_handle.is_none() {
+        commands::start_watcher(session, file_id);
+    }
+
+    Response::FollowChanged { enabled }
+}
@@ -0,0 +1,130 @@
+use std::{collections::HashMap, time::Instant};
+
+use crate::{
+    services::{
+        FileState,
+        commands::{
+            search::SearchMatcher,
+            utils::{self, timestamp},
+        },
+    },
+    types::{CodedError, ErrorCode, LogFormat, Response},
+};
+
+const MAX_BUCKETS: u32 = 10_000;
+
+/// Counts the lines of the whole file in `buckets` equal time intervals between
+/// the oldest and the newest timestamp, lines without a timestamp are counted apart
+pub fn time_histogram(fs: &FileState, buckets: u32, file_id: &Option<String>) -> Response {
+    let started_at = Instant::now();
+
+    if let Err(err) = check_buckets(buckets) {
+        return err.into();
+    }
+
+    let log_format = timestamp::timestamp_format(fs);
+    let ((per_second, unknown), complete) =
+        count_per_second(fs, &log_format, None, "Counting", file_id);
+    let (counts, boundaries) = bin(&per_second, buckets as u64, &log_format);
+
+    Response::TimeHistogram {
+        counts,
+        boundaries,
+        unknown,
+        complete,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
+
+pub(super) fn check_buckets(buckets: u32) -> Result<(), CodedError> {
+    if buckets == 0 || buckets > MAX_BUCKETS {
+        return Err(CodedError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "buckets ({}) must be between 1 and {}",
+                buckets, MAX_BUCKETS
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Counts the lines of each second over the whole file, and the lines without a timestamp
+/// With a matcher, only the lines it matches are counted
+/// The bool is false when a chunk couldn't be read, its lines aren't counted
+pub(super) fn count_per_second(
+    fs: &FileState,
+    log_format: &LogFormat,
+    matcher: Option<&SearchMatcher>,
+    label: &'static str,
+    file_id: &Option<String>,
+) -> ((HashMap<i64, u64>, u64), bool) {
+    // Lines per second first, binned once the time span is known
+    utils::scan_chunks(
+        &fs.processor,
+        label,
+        file_id,
+        |_, lines| count_chunk(log_format, matcher, lines),
+        |(mut per_second, unknown), (other_per_second, other_unknown)| {
+            for (second, count) in other_per_second {
+                *per_second.entry(second).or_insert(0) += count;
+            }
+            (per_second, unknown + other_unknown)
+        },
+    )
+}
+
+fn count_chunk(
+    log_format: &LogFormat,
+    matcher: Option<&SearchMatcher>,
+    lines: &[String],
+) -> (HashMap<i64, u64>, u64) {
+    let mut per_second: HashMap<i64, u64> = HashMap::new();
+    let mut unknown = 0;
+
+    for line in lines {
+        if matcher.is_some_and(|matcher| !matcher.is_match(line)) {
+            continue;
+        }
+
+        match timestamp::parse_timestamp(log_format, line) {
+            Some(ts) => *per_second.entry(ts.to_seconds()).or_insert(0) += 1,
+            None => unknown += 1,
+        }
+    }
+
+    (per_second, unknown)
+}
+
+/// Split [min, max] into buckets of equal length and count the lines in each
+/// Returns the counts and the buckets + 1 boundaries, nothing when no line had a timestamp
+pub(super) fn bin(
+    per_second: &HashMap<i64, u64>,
+    buckets: u64,
+    log_format: &LogFormat,
+) -> (Vec<u64>, Vec<String>) {
+    let (Some(&min), Some(&max)) = (per_second.keys().min(), per_second.keys().max()) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    // Whole seconds, up to the end of the newest one
+    let span = (max - min + 1) as u128;
+
+    let mut counts = vec![0; buckets as usize];
+    for (&second, &count) in per_second {
+        let bucket = (second - min) as u128 * buckets as u128 / span;
+        counts[bucket as usize] += count;
+    }
+
+    // Bucket i starts at the first second that falls in it, the last boundary
+    // is the second after the newest timestamp
+    let without_year = *log_format == LogFormat::SyslogRFC3164;
+    let boundaries = (0..=buckets)
+        .map(|i| {
+            let offset = (i as u128 * span).div_ceil(buckets as u128) as i64;
+            timestamp::Timestamp::from_seconds(min + offset, without_year).to_string()
+        })
+        .collect();
+
+    (counts, boundaries)
+}
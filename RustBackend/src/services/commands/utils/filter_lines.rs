@@ -0,0 +1,293 @@
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    services::{FileProcessor, FileState, commands},
+    types::{CodedError, ErrorCode, Response},
+};
+
+/// Lines per parallel chunk when the client doesn't say
+pub const DEFAULT_CHUNK_SIZE: usize = 10_000;
+const CHUNKS_PER_BATCH: usize = 32; // Chunks scanned in parallel before checking the limit
+const MAX_MATCHES: usize = 10_000_000; // Stop after keeping 10M lines (80MB of line numbers)
+const MAX_DISTINCT_BYTES: usize = 32 << 20; // Text of the values a distinct filter remembers (32MB)
+
+/// Lines returned with the filter results, the others are read with GetFilterPage
+pub const FIRST_PAGE_LINES: u64 = 1_000;
+
+/// Line numbers kept by the last filter, read page by page
+#[derive(Debug, Default)]
+pub struct FilterMatches {
+    pub lines: Vec<u64>,
+    /// False when the filter stopped after MAX_MATCHES lines, a chunk couldn't be read,
+    /// or a distinct filter dropped new values past MAX_DISTINCT_BYTES
+    pub complete: bool,
+    /// For a distinct filter, the number of matching lines with the same value as each kept line
+    pub occurrences: Option<Vec<u64>>,
+}
+
+/// Value a distinct filter compares lines by: whether it's a column of a parsed line,
+/// and the column's text or the whole line
+type DistinctKey = (bool, String);
+
+/// Lines per parallel chunk asked for by the client, DEFAULT_CHUNK_SIZE when None
+pub fn chunk_size(requested: Option<usize>) -> Result<usize, CodedError> {
+    match requested {
+        Some(0) => Err(CodedError::new(
+            ErrorCode::InvalidArgument,
+            "chunk_size must be at least 1",
+        )),
+        requested => Ok(requested.unwrap_or(DEFAULT_CHUNK_SIZE)),
+    }
+}
+
+/// Scans the whole file, `chunk_size` lines per parallel task, and keeps the numbers
+/// of the lines accepted by `keep`
+/// They replace the previous filter's, and the first page of them is returned
+pub fn filter_lines<F>(
+    fs: &mut FileState,
+    chunk_size: usize,
+    file_id: &Option<String>,
+    keep: F,
+) -> Response
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    fs.filter = Some(scan_matches(&fs.processor, chunk_size, file_id, keep));
+    commands::get_filter_page(fs, 0, FIRST_PAGE_LINES)
+}
+
+/// Chunks are scanned in parallel, batch by batch, so the scan can stop once
+/// MAX_MATCHES lines have been kept without losing the ordering
+/// Only line numbers are kept: lines are read and parsed again when a page is asked for
+fn scan_matches<F>(
+    processor: &FileProcessor,
+    chunk_size: usize,
+    file_id: &Option<String>,
+    keep: F,
+) -> FilterMatches
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    let line_count = processor.index.len();
+    let total_chunks = line_count.div_ceil(chunk_size);
+
+    let mut lines: Vec<u64> = Vec::new();
+    let mut complete = true;
+    let chunk_failed = AtomicBool::new(false);
+    let progress = commands::utils::LineProgress::start("Filtering", line_count as u64, file_id);
+
+    for batch_start in (0..total_chunks).step_by(CHUNKS_PER_BATCH) {
+        let batch_end = (batch_start + CHUNKS_PER_BATCH).min(total_chunks);
+
+        let batch: Vec<Vec<u64>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|chunk| {
+                let start_line = chunk * chunk_size;
+                let count = chunk_size.min(line_count - start_line);
+
+                let kept = filter_chunk(processor, &keep, start_line, count).unwrap_or_else(|e| {
+                    // Log error but continue filtering other chunks, the result is incomplete
+                    chunk_failed.store(true, Ordering::Relaxed);
+                    let response = Response::Info {
+                        message: format!(
+                            "Failed to filter chunk starting at line {}: {}",
+                            start_line, e
+                        ),
+                    };
+                    eprintln!("{}", serde_json::to_string(&response).unwrap());
+
+                    Vec::new()
+                });
+
+                progress.advance(count as u64);
+                kept
+            })
+            .collect();
+
+        lines.extend(batch.into_iter().flatten());
+
+        if lines.len() >= MAX_MATCHES {
+            complete = batch_end == total_chunks && lines.len() == MAX_MATCHES;
+            lines.truncate(MAX_MATCHES);
+            break;
+        }
+    }
+
+    progress.finish();
+
+    FilterMatches {
+        lines,
+        complete: complete && !chunk_failed.load(Ordering::Relaxed),
+        occurrences: None,
+    }
+}
+
+/// Like filter_lines, but only the first line of each value is kept, with the number of
+/// matching lines sharing it. The value is the whole line, or `column` of the parsed line
+/// (lines that don't parse are then compared as a whole)
+pub fn filter_distinct_lines<F>(
+    fs: &mut FileState,
+    chunk_size: usize,
+    column: Option<usize>,
+    file_id: &Option<String>,
+    keep: F,
+) -> Response
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    fs.filter = Some(scan_distinct(fs, chunk_size, column, file_id, keep));
+    commands::get_filter_page(fs, 0, FIRST_PAGE_LINES)
+}
+
+/// Chunks are scanned in parallel, each one collapsing its own duplicates, then merged in
+/// file order so the first line of each value is the one kept
+fn scan_distinct<F>(
+    fs: &FileState,
+    chunk_size: usize,
+    column: Option<usize>,
+    file_id: &Option<String>,
+    keep: F,
+) -> FilterMatches
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    let line_count = fs.processor.index.len();
+    let total_chunks = line_count.div_ceil(chunk_size);
+
+    let mut first_lines: HashMap<DistinctKey, usize> = HashMap::new(); // Index of each value in lines
+    let mut distinct_bytes = 0;
+    let mut lines: Vec<u64> = Vec::new();
+    let mut occurrences: Vec<u64> = Vec::new();
+    let mut complete = true;
+    let chunk_failed = AtomicBool::new(false);
+    let progress = commands::utils::LineProgress::start("Filtering", line_count as u64, file_id);
+
+    for batch_start in (0..total_chunks).step_by(CHUNKS_PER_BATCH) {
+        let batch_end = (batch_start + CHUNKS_PER_BATCH).min(total_chunks);
+
+        let batch: Vec<Vec<(u64, DistinctKey, u64)>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|chunk| {
+                let start_line = chunk * chunk_size;
+                let count = chunk_size.min(line_count - start_line);
+
+                let kept =
+                    distinct_chunk(fs, &keep, column, start_line, count).unwrap_or_else(|e| {
+                        // Log error but continue filtering other chunks, the result is incomplete
+                        chunk_failed.store(true, Ordering::Relaxed);
+                        let response = Response::Info {
+                            message: format!(
+                                "Failed to filter chunk starting at line {}: {}",
+                                start_line, e
+                            ),
+                        };
+                        eprintln!("{}", serde_json::to_string(&response).unwrap());
+
+                        Vec::new()
+                    });
+
+                progress.advance(count as u64);
+                kept
+            })
+            .collect();
+
+        for (line, key, count) in batch.into_iter().flatten() {
+            if let Some(&index) = first_lines.get(&key) {
+                occurrences[index] += count;
+            } else if distinct_bytes + key.1.len() <= MAX_DISTINCT_BYTES {
+                distinct_bytes += key.1.len();
+                first_lines.insert(key, lines.len());
+                lines.push(line);
+                occurrences.push(count);
+            } else if complete {
+                complete = false;
+                let response = Response::Info {
+                    message: format!(
+                        "The distinct filter keeps at most {}MB of distinct values, lines with new values from line {} on are left out",
+                        MAX_DISTINCT_BYTES >> 20,
+                        line
+                    ),
+                };
+                eprintln!("{}", serde_json::to_string(&response).unwrap());
+            }
+        }
+    }
+
+    progress.finish();
+
+    FilterMatches {
+        lines,
+        complete: complete && !chunk_failed.load(Ordering::Relaxed),
+        occurrences: Some(occurrences),
+    }
+}
+
+/// First line of each value among the chunk's matching lines, with its value and its count
+fn distinct_chunk<F>(
+    fs: &FileState,
+    keep: &F,
+    column: Option<usize>,
+    start_line: usize,
+    count: usize,
+) -> Result<Vec<(u64, DistinctKey, u64)>, String>
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    let lines = fs
+        .processor
+        .scan_lines_range(start_line as u64, (start_line + count - 1) as u64)?;
+
+    // First line and count of each value
+    let mut values: HashMap<DistinctKey, (u64, u64)> = HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !keep(line) {
+            continue;
+        }
+
+        let value = match (column, &fs.line_parser) {
+            (Some(column), Some(parser)) => {
+                let line = commands::utils::clean_line(line, fs.processor.strip_ansi());
+                commands::utils::parse_line(parser, fs.nbr_columns, &line).and_then(
+                    |mut columns| (column < columns.len()).then(|| columns.swap_remove(column)),
+                )
+            }
+            _ => None,
+        };
+        let key = match value {
+            Some(value) => (true, value),
+            None => (false, line.clone()),
+        };
+
+        values.entry(key).or_insert(((start_line + i) as u64, 0)).1 += 1;
+    }
+
+    let mut kept: Vec<(u64, DistinctKey, u64)> = values
+        .into_iter()
+        .map(|(key, (line, count))| (line, key, count))
+        .collect();
+    kept.sort_unstable_by_key(|&(line, _, _)| line);
+    Ok(kept)
+}
+
+fn filter_chunk<F>(
+    processor: &FileProcessor,
+    keep: &F,
+    start_line: usize,
+    count: usize,
+) -> Result<Vec<u64>, String>
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    let lines = processor.scan_lines_range(start_line as u64, (start_line + count - 1) as u64)?;
+
+    Ok(lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| keep(line))
+        .map(|(i, _)| (start_line + i) as u64)
+        .collect())
+}
@@ -1,5 +1,6 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Deserialize;
 
 use crate::types::LogFormat;
 
@@ -28,6 +29,15 @@ static CLF_PATTERN: Lazy<Regex> = Lazy::new(|| {
     .unwrap()
 });
 
+// Kubernetes CRI: 2024-01-02T15:04:05.000000000Z stdout F message
+// The tag is F for a full line, P for a partial one continued on the next line
+static CRI_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+\-]\d{2}:\d{2})) (stdout|stderr) ([FP]) (.*)$",
+    )
+    .unwrap()
+});
+
 static SYSLOG_3164_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         r"^<(\d{1,3})>([A-Z][a-z]{2}\s{1,2}\d{1,2}\s\d{2}:\d{2}:\d{2})\s(\S+)\s([^:]+):\s(.*)$",
@@ -46,12 +56,14 @@ pub fn get_pattern(format: &LogFormat) -> Option<Regex> {
         LogFormat::NCSACombined => Some(NCSA_PATTERN.clone()),
         LogFormat::CommonLogFormat => Some(CLF_PATTERN.clone()),
         LogFormat::SyslogRFC3164 => Some(SYSLOG_3164_PATTERN.clone()),
-        LogFormat::Other => None,
+        LogFormat::CriLog => Some(CRI_PATTERN.clone()),
+        LogFormat::DockerJson | LogFormat::Other => None,
     }
 }
 
 /// Get the number of columns (capture groups) for a specific log format
 /// Returns None for LogFormat::Other
+/// Docker JSON lines aren't parsed with a regex but still have their 3 columns
 pub fn get_column_count(format: &LogFormat) -> Option<u8> {
     match format {
         LogFormat::CommonEventFormat => Some(8),  // version, vendor, product, device_version, signature_id, name, severity, extension
@@ -60,15 +72,124 @@ pub fn get_column_count(format: &LogFormat) -> Option<u8> {
         LogFormat::NCSACombined => Some(5),        // IP, timestamp, request, status, size
         LogFormat::CommonLogFormat => Some(8),     // host, ident, timestamp, method, path, protocol, status, bytes
         LogFormat::SyslogRFC3164 => Some(5),       // priority, timestamp, hostname, tag, message
+        LogFormat::DockerJson => Some(3),          // time, stream, log
+        LogFormat::CriLog => Some(4),              // time, stream, tag, message
+        LogFormat::Other => None,
+    }
+}
+
+/// Get the column names for a specific log format, in capture group order
+/// Returns None for LogFormat::Other
+pub fn get_column_names(format: &LogFormat) -> Option<&'static [&'static str]> {
+    match format {
+        LogFormat::CommonEventFormat => Some(&[
+            "version", "vendor", "product", "device_version", "signature_id", "name", "severity", "extension",
+        ]),
+        LogFormat::W3CExtended => Some(&["date", "time", "field1", "field2", "field3"]),
+        LogFormat::SyslogRFC5424 => Some(&[
+            "priority", "timestamp", "hostname", "app_name", "procid", "msgid", "structured_data", "message",
+        ]),
+        LogFormat::NCSACombined => Some(&["ip", "timestamp", "request", "status", "size"]),
+        LogFormat::CommonLogFormat => Some(&[
+            "host", "ident", "timestamp", "method", "path", "protocol", "status", "bytes",
+        ]),
+        LogFormat::SyslogRFC3164 => Some(&["priority", "timestamp", "hostname", "tag", "message"]),
+        LogFormat::DockerJson => Some(&["time", "stream", "log"]),
+        LogFormat::CriLog => Some(&["time", "stream", "tag", "message"]),
         LogFormat::Other => None,
     }
 }
 
+/// A line written by Docker's json-file logging driver:
+/// {"log":"message\n","stream":"stdout","time":"2024-01-02T15:04:05.000000000Z"}
+#[derive(Deserialize)]
+struct DockerLine {
+    log: String,
+    #[serde(default)]
+    stream: String,
+    time: String,
+}
+
+/// Columns of a Docker JSON line: time, stream and log (without its trailing newline)
+/// None when the line isn't a JSON object with at least "log" and "time"
+pub fn parse_docker_json(line: &str) -> Option<Vec<String>> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let parsed: DockerLine = serde_json::from_str(line).ok()?;
+
+    let log = parsed.log.strip_suffix('\n').unwrap_or(&parsed.log);
+    let log = log.strip_suffix('\r').unwrap_or(log);
+    Some(vec![parsed.time, parsed.stream, log.to_string()])
+}
+
+/// W3C logs declare their columns in a directive, e.g. "#Fields: date time cs-method cs-uri-stem"
+pub const W3C_FIELDS_DIRECTIVE: &str = "#Fields:";
+
+/// Field names of a W3C #Fields directive line, None for any other line
+pub fn parse_w3c_fields(line: &str) -> Option<Vec<String>> {
+    let fields = line.strip_prefix(W3C_FIELDS_DIRECTIVE)?;
+    let names: Vec<String> = fields.split_whitespace().map(|name| name.to_string()).collect();
+    (!names.is_empty()).then_some(names)
+}
+
+/// Name of the single column of an unparsed line
+pub const RAW_COLUMN_NAME: &str = "line";
+
+/// Resolve the column names of the active parsing settings
+/// Named capture groups win, then the format's names when its built-in pattern is used,
+/// then "column_N". Without a pattern, lines have a single RAW_COLUMN_NAME column
+pub fn resolve_column_names(log_format: Option<&LogFormat>, regex: Option<&Regex>) -> Vec<String> {
+    let Some(regex) = regex else {
+        return vec![RAW_COLUMN_NAME.to_string()];
+    };
+
+    let format_names = log_format
+        .filter(|format| get_pattern(format).is_some_and(|p| p.as_str() == regex.as_str()))
+        .and_then(get_column_names);
+
+    regex
+        .capture_names()
+        .skip(1)
+        .enumerate()
+        .map(|(i, name)| {
+            name.or_else(|| format_names.and_then(|names| names.get(i).copied()))
+                .map_or_else(|| format!("column_{}", i), |name| name.to_string())
+        })
+        .collect()
+}
+
+/// How many lines from the top of a file are looked at to find its first entry
+pub const LEADING_LINES: u64 = 100;
+
+/// Built-in formats, in the order detect_format tries them
+pub const BUILT_IN_FORMATS: [LogFormat; 8] = [
+    LogFormat::DockerJson,
+    LogFormat::CriLog,
+    LogFormat::CommonEventFormat,
+    LogFormat::W3CExtended,
+    LogFormat::SyslogRFC5424,
+    LogFormat::NCSACombined,
+    LogFormat::CommonLogFormat,
+    LogFormat::SyslogRFC3164,
+];
+
 /// Detect the log format from a line by trying patterns in order of specificity
+/// A leading BOM and indentation are ignored, since the patterns are anchored at the start
 /// Returns LogFormat::Other if no pattern matches
 pub fn detect_format(line: &str) -> LogFormat {
+    let line = line.trim_start_matches('\u{FEFF}').trim_start();
+
     // Try patterns in order of specificity (most specific first)
 
+    // 0. Docker JSON and Kubernetes CRI - container logs, nothing else looks like them
+    if parse_docker_json(line).is_some() {
+        return LogFormat::DockerJson;
+    }
+    if CRI_PATTERN.is_match(line) {
+        return LogFormat::CriLog;
+    }
+
     // 1. Common Event Format (CEF) - very specific prefix
     if CEF_PATTERN.is_match(line) {
         return LogFormat::CommonEventFormat;
@@ -102,3 +223,40 @@ pub fn detect_format(line: &str) -> LogFormat {
     // No match found
     LogFormat::Other
 }
+
+/// Detect the log format from the first non-blank line, so blank lines at the top
+/// of a file don't hide its format
+/// Returns LogFormat::Other if every line is blank
+pub fn detect_format_from_first_entry<S: AsRef<str>>(lines: &[S]) -> LogFormat {
+    lines
+        .iter()
+        .map(AsRef::as_ref)
+        .find(|line| !line.trim_start_matches('\u{FEFF}').trim().is_empty())
+        .map_or(LogFormat::Other, detect_format)
+}
+
+/// Detect the log format from several lines
+/// Each line votes for the format it matches, the most common format wins
+/// Returns LogFormat::Other if no line matches a known format
+pub fn detect_format_from_lines(lines: &[&str]) -> LogFormat {
+    let mut votes: Vec<(LogFormat, usize)> = Vec::new();
+
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let format = detect_format(line);
+        if matches!(format, LogFormat::Other) {
+            continue;
+        }
+
+        match votes.iter_mut().find(|(f, _)| *f == format) {
+            Some((_, count)) => *count += 1,
+            None => votes.push((format, 1)),
+        }
+    }
+
+    // On a tie, the format seen first wins
+    votes
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map_or(LogFormat::Other, |(format, _)| format)
+}
@@ -1,4 +1,17 @@
+pub mod base64;
+mod filter_lines;
 pub mod log_format_patterns;
 mod parse_data;
+mod progress;
+mod scan_chunks;
+pub mod timestamp;
 
-pub use parse_data::parse_data;
+pub use filter_lines::{
+    DEFAULT_CHUNK_SIZE, FilterMatches, chunk_size, filter_distinct_lines, filter_lines,
+};
+pub use parse_data::{
+    LineParser, clean_line, column_count, parse_data, parse_data_nullable, parse_line,
+    parse_line_nullable, split_line,
+};
+pub use progress::LineProgress;
+pub use scan_chunks::scan_chunks;
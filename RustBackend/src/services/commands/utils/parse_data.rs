@@ -1,15 +1,80 @@
-use crate::types::Response;
+use std::borrow::Cow;
 
+use crate::{
+    services::{FileProcessor, commands::utils::log_format_patterns},
+    types::Response,
+};
+
+/// How a line is split into columns
+pub enum LineParser {
+    /// Columns are the regex's capture groups, without surrounding whitespace when trim is set
+    Regex { regex: regex::Regex, trim: bool },
+    /// Columns are separated by a delimiter, see split_delimited
+    Delimited(char),
+    /// Whitespace separated columns, named by a W3C #Fields directive
+    /// Directive lines (starting with #) aren't parsed
+    W3CFields(Vec<String>),
+    /// Time, stream and log of a Docker JSON log line
+    DockerJson,
+}
+
+/// Splits lines into columns, lines that don't parse are a single raw column
+/// With strip_ansi, ANSI escape sequences are removed first, from the raw columns too
 pub fn parse_data(
-    regex_pattern: &Option<regex::Regex>,
+    parser: &Option<LineParser>,
     nbr_columns: Option<u8>,
+    strip_ansi: bool,
     data: &[String],
     start_line: u64,
     show_errors: bool,
 ) -> Vec<Vec<String>> {
-    // If no regex, just wrap each line
-    let Some(regex) = regex_pattern else {
-        return data.iter().map(|line| vec![line.clone()]).collect();
+    parse_data_with(
+        parser,
+        strip_ansi,
+        data,
+        start_line,
+        show_errors,
+        |line| line.to_string(),
+        |parser, line| parse_line(parser, nbr_columns, line),
+    )
+}
+
+/// Like parse_data, but optional groups that didn't participate in the match
+/// are kept as None instead of being dropped, so columns stay aligned
+pub fn parse_data_nullable(
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+    strip_ansi: bool,
+    data: &[String],
+    start_line: u64,
+    show_errors: bool,
+) -> Vec<Vec<Option<String>>> {
+    parse_data_with(
+        parser,
+        strip_ansi,
+        data,
+        start_line,
+        show_errors,
+        |line| Some(line.to_string()),
+        |parser, line| parse_line_nullable(parser, nbr_columns, line),
+    )
+}
+
+fn parse_data_with<T>(
+    parser: &Option<LineParser>,
+    strip_ansi: bool,
+    data: &[String],
+    start_line: u64,
+    show_errors: bool,
+    raw: impl Fn(&str) -> T,
+    parse: impl Fn(&LineParser, &str) -> Option<Vec<T>>,
+) -> Vec<Vec<T>> {
+    // If no parser, just wrap each line
+    let Some(parser) = parser else {
+        return data
+            .iter()
+            .map(|line| vec![raw(&clean_line(line, strip_ansi))])
+            .collect();
     };
 
     // Only track first 6 failed lines (5 to show + 1 to detect "more")
@@ -18,34 +83,12 @@ pub fn parse_data(
     let mut results = Vec::new();
 
     for (i, line) in data.iter().enumerate() {
-        if let Some(caps) = regex.captures(line) {
-            // Extract capture groups (skip index 0 which is the full match)
-            let groups: Vec<String> = caps
-                .iter()
-                .skip(1)
-                .filter_map(|m| m.map(|m| m.as_str().to_string()))
-                .collect();
-
-            // Validate column count if user provided one
-            let is_valid = if let Some(expected) = nbr_columns {
-                groups.len() == expected as usize
-            } else {
-                true
-            };
-
-            if is_valid {
-                results.push(groups);
-            } else {
-                // Column count mismatch - fall back to raw line
-                results.push(vec![line.clone()]);
-                if failed_lines.len() < 6 {
-                    failed_lines.push(start_line + i as u64);
-                }
-                total_failures += 1;
-            }
+        let line = clean_line(line, strip_ansi);
+        if let Some(groups) = parse(parser, &line) {
+            results.push(groups);
         } else {
-            // Regex didn't match - fall back to raw line
-            results.push(vec![line.clone()]);
+            // Regex didn't match or column count mismatch - fall back to raw line
+            results.push(vec![raw(&line)]);
             if failed_lines.len() < 6 {
                 failed_lines.push(start_line + i as u64);
             }
@@ -71,3 +114,183 @@ pub fn parse_data(
 
     results
 }
+
+/// A line as it's parsed: without ANSI escape sequences when strip_ansi is set
+pub fn clean_line(line: &str, strip_ansi: bool) -> Cow<'_, str> {
+    if strip_ansi && line.contains('\x1b') {
+        Cow::Owned(remove_ansi(line))
+    } else {
+        Cow::Borrowed(line)
+    }
+}
+
+/// Removes ANSI CSI sequences (ESC [ parameters final byte), like colors (\x1b[31m)
+/// and cursor moves; a sequence cut off at the end of the line is removed too
+/// Other escapes are kept as they are
+fn remove_ansi(line: &str) -> String {
+    let mut parts = line.split('\x1b');
+    let mut stripped = String::from(parts.next().unwrap_or_default());
+
+    for part in parts {
+        let Some(sequence) = part.strip_prefix('[') else {
+            stripped.push('\x1b');
+            stripped.push_str(part);
+            continue;
+        };
+
+        // Parameter and intermediate bytes, then the final byte
+        let rest = sequence.trim_start_matches(|c| ('\x20'..='\x3f').contains(&c));
+        let rest = rest
+            .strip_prefix(|c| ('\x40'..='\x7e').contains(&c))
+            .unwrap_or(rest);
+        stripped.push_str(rest);
+    }
+
+    stripped
+}
+
+/// Splits a single line into columns
+/// Returns None if the regex doesn't match or the column count doesn't match nbr_columns
+pub fn parse_line(parser: &LineParser, nbr_columns: Option<u8>, line: &str) -> Option<Vec<String>> {
+    let groups = split_line(parser, line)?;
+
+    // Validate column count if user provided one
+    let is_valid = if let Some(expected) = nbr_columns {
+        groups.len() == expected as usize
+    } else {
+        true
+    };
+
+    is_valid.then_some(groups)
+}
+
+/// Splits a single line into columns, whatever their number
+/// Returns None if the line doesn't match the format
+pub fn split_line(parser: &LineParser, line: &str) -> Option<Vec<String>> {
+    let groups = match parser {
+        LineParser::Regex { regex, trim } => {
+            let caps = regex.captures(line)?;
+
+            // Extract capture groups (skip index 0 which is the full match)
+            caps.iter()
+                .skip(1)
+                .filter_map(|m| m.map(|m| group_text(m.as_str(), *trim)))
+                .collect()
+        }
+        LineParser::Delimited(delimiter) => split_delimited(line, *delimiter),
+        LineParser::W3CFields(_) => split_w3c_fields(line)?,
+        LineParser::DockerJson => log_format_patterns::parse_docker_json(line)?,
+    };
+
+    Some(groups)
+}
+
+/// A captured group as a column, trimmed of surrounding whitespace with `trim`
+fn group_text(group: &str, trim: bool) -> String {
+    if trim { group.trim() } else { group }.to_string()
+}
+
+/// Splits a single line into columns, keeping None for groups that didn't match
+/// nbr_columns is checked against the groups that matched, like parse_line
+pub fn parse_line_nullable(
+    parser: &LineParser,
+    nbr_columns: Option<u8>,
+    line: &str,
+) -> Option<Vec<Option<String>>> {
+    let groups: Vec<Option<String>> = match parser {
+        LineParser::Regex { regex, trim } => {
+            let caps = regex.captures(line)?;
+
+            caps.iter()
+                .skip(1)
+                .map(|m| m.map(|m| group_text(m.as_str(), *trim)))
+                .collect()
+        }
+        // Every field of a delimited line is present, possibly empty
+        LineParser::Delimited(delimiter) => split_delimited(line, *delimiter)
+            .into_iter()
+            .map(Some)
+            .collect(),
+        LineParser::W3CFields(_) => split_w3c_fields(line)?.into_iter().map(Some).collect(),
+        LineParser::DockerJson => log_format_patterns::parse_docker_json(line)?
+            .into_iter()
+            .map(Some)
+            .collect(),
+    };
+
+    let is_valid = if let Some(expected) = nbr_columns {
+        groups.iter().flatten().count() == expected as usize
+    } else {
+        true
+    };
+
+    is_valid.then_some(groups)
+}
+
+/// Splits a line on the delimiter
+/// With commas, a field can be quoted to contain commas ("a, b"),
+/// and quotes inside a quoted field are doubled ("say ""hi""")
+pub fn split_delimited(line: &str, delimiter: char) -> Vec<String> {
+    if delimiter != ',' {
+        return line
+            .split(delimiter)
+            .map(|field| field.to_string())
+            .collect();
+    }
+
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            // Quotes only open a quoted field at its start
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+/// W3C fields are separated by spaces, a "-" stands for an empty field and is kept as-is
+fn split_w3c_fields(line: &str) -> Option<Vec<String>> {
+    if line.starts_with('#') {
+        return None;
+    }
+    Some(
+        line.split_whitespace()
+            .map(|field| field.to_string())
+            .collect(),
+    )
+}
+
+/// Number of columns lines are split into, 1 without a parser
+/// Delimited lines without nbr_columns are assumed to have as many columns as the first line
+pub fn column_count(
+    processor: &FileProcessor,
+    parser: &Option<LineParser>,
+    nbr_columns: Option<u8>,
+) -> usize {
+    match (parser, nbr_columns) {
+        (None, _) => 1,
+        (Some(_), Some(columns)) => columns as usize,
+        (Some(LineParser::Regex { regex, .. }), None) => regex.captures_len() - 1,
+        (Some(LineParser::W3CFields(names)), None) => names.len(),
+        (Some(LineParser::DockerJson), None) => 3,
+        (Some(LineParser::Delimited(delimiter)), None) => processor
+            .read_line(0)
+            .map_or(1, |line| split_delimited(&line, *delimiter).len()),
+    }
+}
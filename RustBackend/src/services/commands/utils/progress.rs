@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use crate::{
+    services::{self, commands::search::QUIET_SEARCH_LINES},
+    types::Response,
+};
+
+/// Reports how many lines a long operation went through, as Progress events
+/// An event is sent each time the percentage reaches a new whole number, from any thread
+/// Like searches, operations on fewer than QUIET_SEARCH_LINES lines send no events
+pub struct LineProgress {
+    label: &'static str,
+    total: u64,
+    quiet: bool,
+    /// Session the operation runs in, its events are tagged with it
+    file_id: Option<String>,
+    done: AtomicU64,
+    last_percent: AtomicU8,
+}
+
+impl LineProgress {
+    /// Sends the 0% event of a long enough operation, `label` starts the message (e.g. "Filtering")
+    pub fn start(label: &'static str, total: u64, file_id: &Option<String>) -> Self {
+        let progress = Self {
+            label,
+            total,
+            quiet: total < QUIET_SEARCH_LINES as u64,
+            file_id: file_id.clone(),
+            done: AtomicU64::new(0),
+            last_percent: AtomicU8::new(0),
+        };
+        progress.send(0.0, 0);
+        progress
+    }
+
+    /// Counts `lines` more lines as done
+    pub fn advance(&self, lines: u64) {
+        let done = self.done.fetch_add(lines, Ordering::Relaxed) + lines;
+        if self.quiet {
+            return;
+        }
+
+        // 100% is only sent by finish
+        // Widened so the product can't overflow on huge line counts
+        let percent = (done as u128 * 100 / self.total as u128).min(99) as u8;
+        let last = self.last_percent.load(Ordering::Relaxed);
+
+        // Only one thread reports each percentage
+        if percent > last
+            && self
+                .last_percent
+                .compare_exchange(last, percent, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.send((done as f64 * 100.0 / self.total as f64) as f32, done);
+        }
+    }
+
+    /// Sends the 100% event, also when the operation stopped before the end
+    pub fn finish(&self) {
+        self.send(100.0, self.done.load(Ordering::Relaxed));
+    }
+
+    fn send(&self, percent: f32, done: u64) {
+        if self.quiet {
+            return;
+        }
+
+        let response = Response::Progress {
+            percent,
+            message: format!(
+                "{}… {}/{} lines",
+                self.label,
+                short_count(done.min(self.total)),
+                short_count(self.total)
+            ),
+        };
+
+        // Flushed right away so the progress bar moves while the operation runs
+        services::output::send_to(&self.file_id, response);
+    }
+}
+
+/// Formats a count with a K, M or B suffix, e.g. 3.2M for 3_200_000
+fn short_count(count: u64) -> String {
+    let (value, suffix) = match count {
+        0..1_000 => return count.to_string(),
+        1_000..1_000_000 => (count as f64 / 1e3, "K"),
+        1_000_000..1_000_000_000 => (count as f64 / 1e6, "M"),
+        _ => (count as f64 / 1e9, "B"),
+    };
+
+    let value = format!("{:.1}", value);
+    format!("{}{}", value.trim_end_matches(".0"), suffix)
+}
@@ -0,0 +1,65 @@
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    services::{
+        FileProcessor,
+        commands::utils::{DEFAULT_CHUNK_SIZE, LineProgress},
+    },
+    types::Response,
+};
+
+/// Runs `scan` over the whole file, DEFAULT_CHUNK_SIZE lines per parallel task, and combines
+/// the results of the chunks with `merge`. `scan` gets the number of the chunk's first line
+/// and its lines. Progress is reported with `label` (e.g. "Counting")
+/// Like a filter, a chunk that can't be read is left out and the scan goes on,
+/// the returned bool is then false: the result is incomplete
+pub fn scan_chunks<T, S, M>(
+    processor: &FileProcessor,
+    label: &'static str,
+    file_id: &Option<String>,
+    scan: S,
+    merge: M,
+) -> (T, bool)
+where
+    T: Default + Send,
+    S: Fn(u64, &[String]) -> T + Sync,
+    M: Fn(T, T) -> T + Sync + Send,
+{
+    let line_count = processor.index.len();
+    let chunk_failed = AtomicBool::new(false);
+    let progress = LineProgress::start(label, line_count as u64, file_id);
+
+    let result = (0..line_count)
+        .into_par_iter()
+        .step_by(DEFAULT_CHUNK_SIZE)
+        .map(|chunk_start| {
+            let count = DEFAULT_CHUNK_SIZE.min(line_count - chunk_start);
+            let end_line = chunk_start + count - 1;
+
+            let result = match processor.scan_lines_range(chunk_start as u64, end_line as u64) {
+                Ok(lines) => scan(chunk_start as u64, &lines),
+                Err(e) => {
+                    // Log error but continue with the other chunks, the result is incomplete
+                    chunk_failed.store(true, Ordering::Relaxed);
+                    let response = Response::Info {
+                        message: format!(
+                            "Failed to read chunk starting at line {}: {}",
+                            chunk_start, e.message
+                        ),
+                    };
+                    eprintln!("{}", serde_json::to_string(&response).unwrap());
+
+                    T::default()
+                }
+            };
+
+            progress.advance(count as u64);
+            result
+        })
+        .reduce(T::default, merge);
+
+    progress.finish();
+
+    (result, !chunk_failed.load(Ordering::Relaxed))
+}
@@ -0,0 +1,317 @@
+use std::{cmp::Ordering, fmt};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    services::{FileState, commands::utils::log_format_patterns},
+    types::LogFormat,
+};
+
+/// Stands in for the missing year of RFC 3164 timestamps, a leap year so Feb 29 exists
+const YEARLESS_YEAR: i32 = 2000;
+
+// Timestamp layouts found in the supported log formats
+
+// CLF / NCSA: 03/Dec/2011:13:28:06 -0800
+static CLF_TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\[(\d{2})/(\w{3})/(\d{4}):(\d{2}):(\d{2}):(\d{2})(?: ([+-]\d{4}))?").unwrap()
+});
+
+// Syslog RFC 3164: <34>Oct 11 22:14:15 (no year)
+static SYSLOG_3164_TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^<\d{1,3}>(\w{3})\s{1,2}(\d{1,2})\s(\d{2}):(\d{2}):(\d{2})").unwrap()
+});
+
+// ISO 8601 and friends (RFC 5424, W3C, and anything else): 2003-10-11T22:14:15 or 2003-10-11 22:14:15,
+// possibly with fractional seconds and a zone: 2003-10-11T22:14:15.003Z, 2003-10-11T22:14:15+02:00
+static ISO_TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(\d{4})-(\d{2})-(\d{2})[T\s](\d{2}):(\d{2}):(\d{2})(?:[.,]\d+)?(Z|[+-]\d{2}:?\d{2})?",
+    )
+    .unwrap()
+});
+
+// User supplied bounds: a full ISO date-time, or a time of day alone
+static TIME_OF_DAY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{2}):(\d{2})(?::(\d{2}))?$").unwrap());
+static DATE_TIME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d{4})-(\d{2})-(\d{2})[T\s](\d{2}):(\d{2})(?::(\d{2}))?$").unwrap()
+});
+
+/// A timestamp as written in the log line
+/// Only epoch_millis uses the time zone, ranges and histograms use the time as written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: Option<i32>, // RFC 3164 timestamps have no year
+    pub month: u8,
+    pub day: u8,
+    pub time: (u8, u8, u8),
+    pub utc_offset: Option<i32>, // In seconds, None when the line gives no time zone
+}
+
+/// One end of a time range given by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBound {
+    pub date: Option<(i32, u8, u8)>, // None means "any day"
+    pub time: (u8, u8, u8),
+}
+
+/// The format whose timestamp layout applies to the file: the one given to ParseFile,
+/// or the one detected from the first non-blank line when no format was set
+pub fn timestamp_format(fs: &FileState) -> LogFormat {
+    fs.log_format.clone().unwrap_or_else(|| {
+        fs.processor
+            .read_lines_range(0, log_format_patterns::LEADING_LINES - 1)
+            .map_or(LogFormat::Other, |lines| {
+                log_format_patterns::detect_format_from_first_entry(&lines)
+            })
+    })
+}
+
+/// Extract the timestamp of a raw line, using the layout of the given format
+pub fn parse_timestamp(format: &LogFormat, line: &str) -> Option<Timestamp> {
+    match format {
+        LogFormat::CommonLogFormat | LogFormat::NCSACombined => {
+            let caps = CLF_TIMESTAMP.captures(line)?;
+            Some(Timestamp {
+                year: Some(caps[3].parse().ok()?),
+                month: month_from_name(&caps[2])?,
+                day: caps[1].parse().ok()?,
+                time: (
+                    caps[4].parse().ok()?,
+                    caps[5].parse().ok()?,
+                    caps[6].parse().ok()?,
+                ),
+                utc_offset: caps.get(7).and_then(|zone| parse_utc_offset(zone.as_str())),
+            })
+        }
+        LogFormat::SyslogRFC3164 => {
+            let caps = SYSLOG_3164_TIMESTAMP.captures(line)?;
+            Some(Timestamp {
+                year: None,
+                month: month_from_name(&caps[1])?,
+                day: caps[2].parse().ok()?,
+                time: (
+                    caps[3].parse().ok()?,
+                    caps[4].parse().ok()?,
+                    caps[5].parse().ok()?,
+                ),
+                utc_offset: None,
+            })
+        }
+        // The time is in its own field, the log itself may contain other dates
+        LogFormat::DockerJson => {
+            let columns = log_format_patterns::parse_docker_json(line)?;
+            iso_timestamp(&columns[0])
+        }
+        LogFormat::SyslogRFC5424
+        | LogFormat::W3CExtended
+        | LogFormat::CommonEventFormat
+        | LogFormat::CriLog
+        | LogFormat::Other => iso_timestamp(line),
+    }
+}
+
+/// The first ISO 8601 date-time in text
+fn iso_timestamp(text: &str) -> Option<Timestamp> {
+    let caps = ISO_TIMESTAMP.captures(text)?;
+    Some(Timestamp {
+        year: Some(caps[1].parse().ok()?),
+        month: caps[2].parse().ok()?,
+        day: caps[3].parse().ok()?,
+        time: (
+            caps[4].parse().ok()?,
+            caps[5].parse().ok()?,
+            caps[6].parse().ok()?,
+        ),
+        utc_offset: caps.get(7).and_then(|zone| parse_utc_offset(zone.as_str())),
+    })
+}
+
+/// Seconds east of UTC of a zone written "Z", "+0200", "-08:00"...
+fn parse_utc_offset(zone: &str) -> Option<i32> {
+    if zone == "Z" {
+        return Some(0);
+    }
+
+    let digits = zone[1..].replace(':', "");
+    let hours: i32 = digits.get(..2)?.parse().ok()?;
+    let minutes: i32 = digits.get(2..)?.parse().ok()?;
+    let offset = hours * 3_600 + minutes * 60;
+    Some(if zone.starts_with('-') {
+        -offset
+    } else {
+        offset
+    })
+}
+
+/// Parse a range bound: "YYYY-MM-DD HH:MM[:SS]" (a 'T' separator works too) or "HH:MM[:SS]"
+pub fn parse_bound(input: &str) -> Option<TimeBound> {
+    let input = input.trim();
+
+    if let Some(caps) = DATE_TIME.captures(input) {
+        return Some(TimeBound {
+            date: Some((
+                caps[1].parse().ok()?,
+                caps[2].parse().ok()?,
+                caps[3].parse().ok()?,
+            )),
+            time: (
+                caps[4].parse().ok()?,
+                caps[5].parse().ok()?,
+                caps.get(6).map_or(Some(0), |s| s.as_str().parse().ok())?,
+            ),
+        });
+    }
+
+    let caps = TIME_OF_DAY.captures(input)?;
+    Some(TimeBound {
+        date: None,
+        time: (
+            caps[1].parse().ok()?,
+            caps[2].parse().ok()?,
+            caps.get(3).map_or(Some(0), |s| s.as_str().parse().ok())?,
+        ),
+    })
+}
+
+impl Timestamp {
+    /// Compare against a bound, only looking at the parts both sides have
+    pub fn cmp_bound(&self, bound: &TimeBound) -> Ordering {
+        match (bound.date, self.year) {
+            (Some((year, month, day)), Some(own_year)) => {
+                (own_year, self.month, self.day, self.time).cmp(&(year, month, day, bound.time))
+            }
+            // No year in the line: compare month and day only
+            (Some((_, month, day)), None) => {
+                (self.month, self.day, self.time).cmp(&(month, day, bound.time))
+            }
+            // Time of day bound: any day matches
+            (None, _) => self.time.cmp(&bound.time),
+        }
+    }
+
+    /// Whether the timestamp falls in [start, end] (inclusive)
+    pub fn is_within(&self, start: &TimeBound, end: &TimeBound) -> bool {
+        self.cmp_bound(start) != Ordering::Less && self.cmp_bound(end) != Ordering::Greater
+    }
+
+    /// Seconds since 1970-01-01 00:00:00, as if the timestamp were UTC
+    pub fn to_seconds(self) -> i64 {
+        let year = self.year.unwrap_or(YEARLESS_YEAR);
+        let (hours, minutes, seconds) = self.time;
+        days_from_civil(year, self.month, self.day) * 86_400
+            + hours as i64 * 3_600
+            + minutes as i64 * 60
+            + seconds as i64
+    }
+
+    /// Milliseconds since the epoch, None without a year to place the timestamp in
+    /// The time zone written in the line is applied, a timestamp without one is taken as UTC
+    pub fn epoch_millis(self) -> Option<i64> {
+        let offset = self.utc_offset.unwrap_or(0) as i64;
+        self.year.map(|_| (self.to_seconds() - offset) * 1000)
+    }
+
+    /// Inverse of to_seconds, without_year for timestamps that had none
+    pub fn from_seconds(seconds: i64, without_year: bool) -> Self {
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+        let time_of_day = seconds.rem_euclid(86_400);
+        Self {
+            year: (!without_year).then_some(year),
+            month,
+            day,
+            time: (
+                (time_of_day / 3_600) as u8,
+                (time_of_day / 60 % 60) as u8,
+                (time_of_day % 60) as u8,
+            ),
+            utc_offset: None,
+        }
+    }
+}
+
+/// "YYYY-MM-DD HH:MM:SS", or "MM-DD HH:MM:SS" without a year
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(year) = self.year {
+            write!(f, "{:04}-", year)?;
+        }
+        let (hours, minutes, seconds) = self.time;
+        write!(
+            f,
+            "{:02}-{:02} {:02}:{:02}:{:02}",
+            self.month, self.day, hours, minutes, seconds
+        )
+    }
+}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date of a number of days since 1970-01-01, inverse of days_from_civil
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year as i32, month as u8, day as u8)
+}
+
+fn month_from_name(name: &str) -> Option<u8> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(name))
+        .map(|i| i as u8 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch_millis_applies_the_time_zone() {
+        let millis = |format: &LogFormat, line: &str| {
+            parse_timestamp(format, line).and_then(Timestamp::epoch_millis)
+        };
+        // 2011-12-03 21:28:06 UTC
+        let expected = Some(1_322_947_686_000);
+
+        let clf = r#"127.0.0.1 - - [03/Dec/2011:13:28:06 -0800] "GET / HTTP/1.1" 200 5"#;
+        assert_eq!(millis(&LogFormat::CommonLogFormat, clf), expected);
+
+        for line in [
+            "2011-12-03T21:28:06Z app started",
+            "2011-12-03T21:28:06.250Z app started",
+            "2011-12-03T23:28:06+02:00 app started",
+            "2011-12-03 16:58:06-0430 app started",
+            "2011-12-03 21:28:06 app started",
+        ] {
+            assert_eq!(millis(&LogFormat::Other, line), expected, "{}", line);
+        }
+    }
+}
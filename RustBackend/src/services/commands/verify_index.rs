@@ -0,0 +1,26 @@
+use crate::{
+    services::FileProcessor,
+    types::{ErrorCode, Response},
+};
+
+/// Compare the index with a new scan of the file, to catch a drifted index in the field
+pub fn verify_index(processor: &FileProcessor) -> Response {
+    let (scanned_lines, divergence) = match processor.verify_index() {
+        Ok(result) => result,
+        Err(e) => {
+            return Response::Error {
+                message: format!("Failed to scan the file again: {}", e),
+                code: Some(ErrorCode::Io),
+            };
+        }
+    };
+
+    Response::IndexVerified {
+        matches: divergence.is_none(),
+        indexed_lines: processor.index.len() as u64,
+        scanned_lines,
+        divergence_line: divergence.as_ref().map(|d| d.line),
+        indexed_newline: divergence.as_ref().and_then(|d| d.indexed_newline),
+        scanned_newline: divergence.as_ref().and_then(|d| d.scanned_newline),
+    }
+}
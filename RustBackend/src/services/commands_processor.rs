@@ -1,73 +1,178 @@
-use std::{
-    sync::{
-        Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
-    },
-    thread::JoinHandle,
-};
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    services::{FileState, commands},
-    types::{Command, Response},
+    services::{
+        OpenCancel, Session,
+        commands::{self, OpenMode, SearchOutput, SearchTarget},
+    },
+    types::{Command, ErrorCode, Response},
 };
 
+/// Session used when a command doesn't specify a file_id
+const DEFAULT_FILE_ID: &str = "default";
+
 pub struct CommandsProcessor {
-    file_state: Arc<Mutex<Option<FileState>>>,
-    watcher_handle: Option<JoinHandle<()>>,
-    should_stop: Arc<AtomicBool>,
+    sessions: HashMap<String, Session>,
+    open_cancel: Arc<OpenCancel>,
 }
 
 impl CommandsProcessor {
     pub fn new() -> Self {
+        let mut sessions = HashMap::new();
+        sessions.insert(DEFAULT_FILE_ID.to_string(), Session::new());
+
         Self {
-            file_state: Arc::new(Mutex::new(None)),
-            watcher_handle: None,
-            should_stop: Arc::new(AtomicBool::new(false)),
+            sessions,
+            open_cancel: Arc::new(OpenCancel::default()),
         }
     }
 
-    pub fn process_command(&mut self, command: Command) -> Response {
+    /// Shared with the stdin reader so it can cancel an OpenFile that is still indexing
+    pub fn open_cancel(&self) -> Arc<OpenCancel> {
+        Arc::clone(&self.open_cancel)
+    }
+
+    pub fn process_command(&mut self, file_id: Option<String>, command: Command) -> Response {
+        let key = session_key(&file_id).to_string();
+
         match command {
             // Handle commands that don't require the file to be opened.
-            Command::GetFileEncoding { path } => commands::get_file_encoding(&path),
+            Command::GetFileEncoding { path, detect_bytes } => {
+                commands::get_file_encoding(&path, detect_bytes)
+            }
+            Command::DetectFormat { path } => commands::detect_format(&path),
+            Command::GetFileInfo { path } => commands::get_file_info(&path),
 
             // Handle the OpenFile command:
-            Command::OpenFile { path } => commands::open_file(
-                &path,
-                &mut self.file_state,
-                &mut self.watcher_handle,
-                &self.should_stop,
-            ),
+            Command::OpenFile { path, options } => {
+                let session = self.sessions.entry(key).or_insert_with(Session::new);
+                commands::open_file(
+                    &path,
+                    session,
+                    file_id,
+                    options,
+                    OpenMode::Whole,
+                    &self.open_cancel,
+                )
+            }
+
+            // Open a file at its end, skipping its history:
+            Command::OpenTail { path, options } => {
+                let session = self.sessions.entry(key).or_insert_with(Session::new);
+                commands::open_file(
+                    &path,
+                    session,
+                    file_id,
+                    options,
+                    OpenMode::Tail,
+                    &self.open_cancel,
+                )
+            }
+
+            // Open only the lines of a byte range of the file:
+            Command::OpenWindow {
+                path,
+                start_byte,
+                end_byte,
+                options,
+            } => {
+                let session = self.sessions.entry(key).or_insert_with(Session::new);
+                commands::open_file(
+                    &path,
+                    session,
+                    file_id,
+                    options,
+                    OpenMode::Window(start_byte, end_byte),
+                    &self.open_cancel,
+                )
+            }
+
+            // CancelOpen is handled by the stdin reader while OpenFile runs; by the time
+            // it gets here the indexing it targeted is already over
+            Command::CancelOpen => Response::Info {
+                message: String::from("No file is being opened."),
+            },
+
+            // Handle the CloseFile command (dropping the session stops its watcher):
+            Command::CloseFile => {
+                if self.sessions.remove(&key).is_none() {
+                    return Response::Error {
+                        message: format!("No file is opened with file_id {}.", key),
+                        code: Some(ErrorCode::NotOpen),
+                    };
+                }
+                if key == DEFAULT_FILE_ID {
+                    self.sessions.insert(key, Session::new());
+                }
+                Response::FileClosed
+            }
+
+            // Start or stop the session's watcher
+            Command::SetFollow { enabled } => {
+                let Some(session) = self.sessions.get_mut(&key) else {
+                    return Response::Error {
+                        message: format!("No file is opened with file_id {}.", key),
+                        code: Some(ErrorCode::NotOpen),
+                    };
+                };
+                commands::set_follow(session, file_id, enabled)
+            }
 
             // Handle the ParseFile command (needs to modify file_state):
             Command::ParseFile {
                 log_format,
                 pattern,
                 nbr_columns,
+                sample_lines,
+                strip_ansi,
+                trim,
             } => {
-                let file_state = Arc::clone(&self.file_state);
-                commands::parse_file(file_state, log_format, pattern, nbr_columns)
+                let Some(session) = self.sessions.get(&key) else {
+                    return Response::Error {
+                        message: format!("No file is opened with file_id {}.", key),
+                        code: Some(ErrorCode::NotOpen),
+                    };
+                };
+                let file_state = Arc::clone(&session.file_state);
+                commands::parse_file(
+                    file_state,
+                    log_format,
+                    pattern,
+                    nbr_columns,
+                    sample_lines,
+                    strip_ansi,
+                    trim,
+                )
             }
 
             // Handle all other commands that require an open file:
             other_command => {
                 // For all other commands, ensure a file is opened first
-                let guard = match self.file_state.lock() {
+                let Some(session) = self.sessions.get(&key) else {
+                    return Response::Error {
+                        message: format!("No file is opened with file_id {}.", key),
+                        code: Some(ErrorCode::NotOpen),
+                    };
+                };
+
+                let mut guard = match session.file_state.lock() {
                     Ok(g) => g,
                     Err(_poisoned) => {
                         let response = Response::Error {
                             message: "FileState mutex is poisoned.".to_string(),
+                            code: Some(ErrorCode::Internal),
                         };
                         return response;
                     }
                 };
 
-                let fs = match guard.as_ref() {
+                let fs = match guard.as_mut() {
                     Some(f) => f,
                     None => {
                         let response = Response::Error {
                             message: "use OpenFile command before parsing file contents."
                                 .to_string(),
+                            code: Some(ErrorCode::NotOpen),
                         };
                         return response;
                     }
@@ -77,32 +182,284 @@ impl CommandsProcessor {
                     Command::GetParsingInformation => {
                         commands::get_parsing_information(&fs.processor)
                     }
+                    Command::ProbeFormats { sample_lines } => {
+                        commands::probe_formats(&fs.processor, sample_lines)
+                    }
+                    Command::GetColumnNames => Response::ColumnNames {
+                        names: commands::column_names(fs),
+                    },
+                    Command::ParseDelimited {
+                        delimiter,
+                        nbr_columns,
+                    } => commands::parse_delimited(fs, delimiter, nbr_columns),
+                    // Filtered line numbers don't survive a rebuilt index
+                    Command::Reindex => {
+                        fs.filter = None;
+                        match fs.processor.reindex() {
+                            Ok(()) => Response::Reindexed {
+                                line_count: fs.processor.index.len() as u64,
+                                trailing_newline: fs.processor.has_trailing_newline(),
+                            },
+                            Err(err) => err.into(),
+                        }
+                    }
+                    Command::SetEncoding { label } => {
+                        fs.filter = None;
+                        commands::set_encoding(&mut fs.processor, &label)
+                    }
                     Command::GetChunk {
                         start_line,
                         end_line,
+                        explicit_nulls,
+                        reverse,
+                        include_raw,
+                        max_column_bytes,
                     } => commands::get_chunk(
                         &fs.processor,
                         start_line,
                         end_line,
-                        &fs.regex_pattern,
+                        &fs.line_parser,
                         fs.nbr_columns,
+                        commands::ChunkOutput {
+                            explicit_nulls,
+                            reverse,
+                            include_raw,
+                            max_column_bytes,
+                            as_written: false,
+                        },
                     ),
-                    Command::Search { pattern } => {
-                        // Compile the search regex
-                        match regex::Regex::new(&pattern) {
-                            Ok(search_regex) => commands::search(
+                    Command::GetChunkCount {
+                        start_line,
+                        count,
+                        explicit_nulls,
+                        reverse,
+                        include_raw,
+                        max_column_bytes,
+                    } => commands::get_chunk_count(
+                        &fs.processor,
+                        start_line,
+                        count,
+                        &fs.line_parser,
+                        fs.nbr_columns,
+                        commands::ChunkOutput {
+                            explicit_nulls,
+                            reverse,
+                            include_raw,
+                            max_column_bytes,
+                            as_written: false,
+                        },
+                    ),
+                    Command::GetFullColumn {
+                        line,
+                        column,
+                        explicit_nulls,
+                    } => commands::get_full_column(
+                        &fs.processor,
+                        line,
+                        column,
+                        &fs.line_parser,
+                        fs.nbr_columns,
+                        explicit_nulls,
+                    ),
+                    Command::GetChunkByBytes {
+                        start_byte,
+                        end_byte,
+                    } => commands::get_chunk_by_bytes(
+                        &fs.processor,
+                        start_byte,
+                        end_byte,
+                        &fs.line_parser,
+                        fs.nbr_columns,
+                    ),
+                    Command::GetChunkByPercent { percent, window } => {
+                        commands::get_chunk_by_percent(
+                            &fs.processor,
+                            percent,
+                            window,
+                            &fs.line_parser,
+                            fs.nbr_columns,
+                        )
+                    }
+                    Command::GetContext {
+                        line,
+                        before,
+                        after,
+                    } => commands::get_context(
+                        &fs.processor,
+                        line,
+                        before,
+                        after,
+                        &fs.line_parser,
+                        fs.nbr_columns,
+                    ),
+                    Command::VerifyIndex => commands::verify_index(&fs.processor),
+                    Command::GetLineLengths {
+                        start_line,
+                        end_line,
+                    } => commands::get_line_lengths(&fs.processor, start_line, end_line),
+                    Command::GetLines { lines } => {
+                        commands::get_lines(&fs.processor, lines, &fs.line_parser, fs.nbr_columns)
+                    }
+                    Command::GetRawBytes {
+                        start_line,
+                        end_line,
+                    } => commands::get_raw_bytes(&fs.processor, start_line, end_line),
+                    Command::GetRawChunk {
+                        start_line,
+                        end_line,
+                    } => commands::get_raw_chunk(&fs.processor, start_line, end_line),
+                    Command::Search {
+                        pattern,
+                        raw,
+                        anchor_to_line,
+                        case_insensitive,
+                        whole_word,
+                        dot_matches_newline,
+                        ignore_whitespace,
+                        column_names,
+                        stream,
+                        char_offsets,
+                        join_columns,
+                        exclude_columns,
+                        size_limit,
+                        dfa_size_limit,
+                        chunk_size,
+                    } => {
+                        let chunk_size = match commands::chunk_size(chunk_size) {
+                            Ok(chunk_size) => chunk_size,
+                            Err(err) => return err.into(),
+                        };
+                        let names = column_names.then(|| commands::column_names(fs));
+                        let target = match (&join_columns, &exclude_columns) {
+                            (Some(_), Some(_)) => {
+                                return Response::Error {
+                                    message: String::from(
+                                        "join_columns and exclude_columns can't be used together",
+                                    ),
+                                    code: Some(ErrorCode::InvalidArgument),
+                                };
+                            }
+                            (None, Some(_)) if raw || anchor_to_line => {
+                                return Response::Error {
+                                    message: String::from(
+                                        "exclude_columns searches the parsed columns, it can't be used with raw or anchor_to_line",
+                                    ),
+                                    code: Some(ErrorCode::InvalidArgument),
+                                };
+                            }
+                            (Some(columns), None) => SearchTarget::Joined(columns),
+                            (None, Some(excluded)) => SearchTarget::Columns(excluded),
+                            (None, None) if raw || anchor_to_line => SearchTarget::Line,
+                            (None, None) => SearchTarget::Columns(&[]),
+                        };
+                        // Compile the search pattern
+                        match commands::build_search_matcher(
+                            &pattern,
+                            case_insensitive,
+                            whole_word,
+                            dot_matches_newline,
+                            ignore_whitespace,
+                            size_limit,
+                            dfa_size_limit,
+                        ) {
+                            Ok(matcher) => commands::search(
                                 &fs.processor,
-                                &fs.regex_pattern,
-                                &search_regex,
+                                &fs.line_parser,
+                                &matcher,
                                 fs.nbr_columns,
+                                target,
+                                SearchOutput {
+                                    column_names: names.as_deref(),
+                                    stream,
+                                    file_id: &file_id,
+                                    char_offsets,
+                                },
+                                chunk_size,
                             ),
-                            Err(e) => Response::Error {
-                                message: format!("Invalid regex pattern: {}", e),
+                            Err(message) => Response::Error {
+                                message,
+                                code: Some(ErrorCode::InvalidRegex),
                             },
                         }
                     }
+                    Command::ExportMatches {
+                        out_path,
+                        line_numbers,
+                        utf8,
+                    } => commands::export_matches(
+                        &fs.processor,
+                        &out_path,
+                        line_numbers,
+                        utf8,
+                        &file_id,
+                    ),
+                    Command::ReplaceToFile {
+                        pattern,
+                        replacement,
+                        out_path,
+                    } => commands::replace_to_file(
+                        &fs.processor,
+                        &pattern,
+                        &replacement,
+                        &out_path,
+                        &file_id,
+                    ),
+                    Command::CountByColumn { column } => {
+                        commands::count_by_column(fs, column, &file_id)
+                    }
+                    Command::ParseStats => commands::parse_stats(fs, &file_id),
+                    Command::TimeHistogram { buckets } => {
+                        commands::time_histogram(fs, buckets, &file_id)
+                    }
+                    Command::MatchTimeline { pattern, buckets } => {
+                        commands::match_timeline(fs, &pattern, buckets, &file_id)
+                    }
+                    Command::NthMatch { pattern, n } => {
+                        commands::nth_match(fs, &pattern, n, &file_id)
+                    }
+                    Command::SearchBytes { hex } => match commands::parse_hex(&hex) {
+                        Ok(needle) => commands::search_bytes(&fs.processor, &needle, &file_id),
+                        Err(message) => Response::Error {
+                            message,
+                            code: Some(ErrorCode::InvalidArgument),
+                        },
+                    },
+                    Command::FilterTimeRange {
+                        start,
+                        end,
+                        keep_unparsed,
+                    } => commands::filter_time_range(fs, &start, &end, keep_unparsed, &file_id),
+                    Command::Filter {
+                        pattern,
+                        chunk_size,
+                        distinct,
+                        distinct_column,
+                    } => match commands::chunk_size(chunk_size) {
+                        Ok(chunk_size) => commands::filter(
+                            fs,
+                            &pattern,
+                            chunk_size,
+                            distinct,
+                            distinct_column,
+                            &file_id,
+                        ),
+                        Err(err) => err.into(),
+                    },
+                    Command::GetFilterPage { offset, count } => {
+                        commands::get_filter_page(fs, offset, count)
+                    }
+                    Command::Extract {
+                        pattern,
+                        chunk_size,
+                    } => match commands::chunk_size(chunk_size) {
+                        Ok(chunk_size) => {
+                            commands::extract(&fs.processor, &pattern, chunk_size, &file_id)
+                        }
+                        Err(err) => err.into(),
+                    },
                     _ => Response::Error {
                         message: String::from("Command not implemented yet"),
+                        code: Some(ErrorCode::Internal),
                     },
                 }
             }
@@ -110,11 +467,7 @@ impl CommandsProcessor {
     }
 }
 
-impl Drop for CommandsProcessor {
-    fn drop(&mut self) {
-        if let Some(handle) = self.watcher_handle.take() {
-            self.should_stop.store(true, Ordering::Relaxed);
-            let _ = handle.join();
-        }
-    }
+/// Key of the session a command is for
+pub fn session_key(file_id: &Option<String>) -> &str {
+    file_id.as_deref().unwrap_or(DEFAULT_FILE_ID)
 }
@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Whether we have a decoder for this format, other formats are read as-is
+    pub fn can_decompress(&self) -> bool {
+        matches!(self, Compression::Zstd)
+    }
+
+    /// Map a format to its decoder, adding a codec means a variant, its magic
+    /// bytes in detect_compression and a branch here
+    fn decoder(&self, file: File) -> std::io::Result<Box<dyn Read>> {
+        match self {
+            Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(file)?)),
+            Compression::Gzip => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "gzip decompression isn't supported",
+            )),
+        }
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Recognize compressed files from their magic bytes
+pub fn detect_compression(file: &mut File) -> std::io::Result<Option<Compression>> {
+    let mut magic = [0u8; 4];
+    file.seek(SeekFrom::Start(0))?;
+    let bytes_read = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if bytes_read >= 2 && magic[..2] == GZIP_MAGIC {
+        Ok(Some(Compression::Gzip))
+    } else if bytes_read == 4 && magic == ZSTD_MAGIC {
+        Ok(Some(Compression::Zstd))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Estimate the decompressed size without decompressing anything
+/// gzip: the ISIZE footer, which is the size modulo 2^32 (wrong for content over 4GB)
+/// and only describes the last member of a multi-member file
+/// zstd: the content size of the first frame, when the compressor wrote it
+pub fn estimate_uncompressed_size(
+    file: &mut File,
+    compression: Compression,
+) -> std::io::Result<Option<u64>> {
+    let estimate = match compression {
+        Compression::Gzip => {
+            let mut footer = [0u8; 4];
+            file.seek(SeekFrom::End(-4))?;
+            file.read_exact(&mut footer)?;
+            Some(u32::from_le_bytes(footer) as u64)
+        }
+        Compression::Zstd => {
+            // Magic number, frame header descriptor, then up to 1 + 4 + 8 bytes of fields
+            let mut header = [0u8; 18];
+            file.seek(SeekFrom::Start(0))?;
+            let bytes_read = file.read(&mut header)?;
+            zstd_frame_content_size(&header[..bytes_read])
+        }
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    Ok(estimate)
+}
+
+/// Read the Frame_Content_Size field of a zstd frame header (RFC 8878, section 3.1.1.1)
+fn zstd_frame_content_size(header: &[u8]) -> Option<u64> {
+    let descriptor = *header.get(4)?;
+    let content_size_flag = descriptor >> 6;
+    let single_segment = (descriptor >> 5) & 1 == 1;
+    let dictionary_id_size = [0, 1, 2, 4][(descriptor & 0b11) as usize];
+
+    let field_size = match content_size_flag {
+        0 if single_segment => 1,
+        0 => return None, // Content size not written
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+
+    let window_descriptor_size = if single_segment { 0 } else { 1 };
+    let start = 5 + window_descriptor_size + dictionary_id_size;
+    let field = header.get(start..start + field_size)?;
+
+    let mut bytes = [0u8; 8];
+    bytes[..field_size].copy_from_slice(field);
+    let value = u64::from_le_bytes(bytes);
+
+    // The 2 byte form is stored with an offset of 256
+    Some(if field_size == 2 { value + 256 } else { value })
+}
+
+/// Used to give each decompressed copy its own name
+static DECOMPRESSED_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Decompress a whole file into a temporary file, so it can be indexed and
+/// read (with seeks) exactly like a plain file
+/// Stops early with an error when `cancelled` is set
+pub fn decompress_to_temp(
+    file: File,
+    compression: Compression,
+    cancelled: &AtomicBool,
+) -> Result<PathBuf, String> {
+    let mut decoder = compression
+        .decoder(file)
+        .map_err(|e| format!("couldn't read {} data: {}", compression.name(), e))?;
+
+    let temp_path = std::env::temp_dir().join(format!(
+        "fatfile-{}-{}.log",
+        std::process::id(),
+        DECOMPRESSED_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = copy_cancellable(&mut decoder, &temp_path, cancelled);
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("couldn't decompress the file: {}", err));
+    }
+
+    Ok(temp_path)
+}
+
+fn copy_cancellable(
+    decoder: &mut dyn Read,
+    temp_path: &Path,
+    cancelled: &AtomicBool,
+) -> std::io::Result<()> {
+    let mut output = File::create(temp_path)?;
+    let mut buffer = vec![0u8; 256 * 1024];
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "decompression was cancelled",
+            ));
+        }
+
+        let bytes_read = decoder.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        output.write_all(&buffer[..bytes_read])?;
+    }
+
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_files::TempFile;
+
+    #[test]
+    fn zstd_round_trip() {
+        let lines: Vec<String> = (0..1_000)
+            .map(|i| format!("2024-01-01 12:00:00 INFO request {} served", i))
+            .collect();
+        let text = lines.join("\n") + "\n";
+        let compressed = zstd::encode_all(text.as_bytes(), 0).unwrap();
+        let file = TempFile::new(&compressed);
+
+        let processor = file.open("utf-8");
+        assert!(matches!(processor.compression, Some(Compression::Zstd)));
+        assert_eq!(processor.index.len(), lines.len());
+        assert_eq!(processor.read_lines_range(0, 999).unwrap(), lines);
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::types::{CodedError, ErrorCode};
+
+/// Opens a file requested by the client
+/// Each common failure gets its own message so the client can suggest the right fix
+pub fn open_checked(path: &str) -> Result<File, CodedError> {
+    let metadata = metadata_checked(path)?;
+    if metadata.is_dir() {
+        return Err(CodedError::new(
+            ErrorCode::InvalidPath,
+            format!("Path is a directory: {}", path),
+        ));
+    }
+
+    File::open(path).map_err(|e| describe_io_error(path, &e))
+}
+
+/// Filesystem facts about a path requested by the client, directories included
+/// Uses the same rules and messages as open_checked
+pub fn metadata_checked(path: &str) -> Result<Metadata, CodedError> {
+    require_absolute(path)?;
+
+    fs::metadata(path).map_err(|e| describe_io_error(path, &e))
+}
+
+/// Creates (or replaces) a file requested by the client as an output
+/// Uses the same rules and messages as open_checked, and refuses the file `source` is read
+/// from: writing over it would destroy what's being read
+pub fn create_checked_output(path: &str, source: &str) -> Result<File, CodedError> {
+    require_absolute(path)?;
+    let target = Path::new(path);
+
+    if let (Ok(source), Ok(target)) = (fs::canonicalize(source), fs::canonicalize(target))
+        && source == target
+    {
+        return Err(CodedError::new(
+            ErrorCode::InvalidPath,
+            format!("Can't write into the opened file: {}", path),
+        ));
+    }
+
+    if target.is_dir() {
+        return Err(CodedError::new(
+            ErrorCode::InvalidPath,
+            format!("Path is a directory: {}", path),
+        ));
+    }
+
+    if let Some(parent) = target.parent()
+        && !parent.is_dir()
+    {
+        return Err(CodedError::new(
+            ErrorCode::FileNotFound,
+            format!("Directory not found: {}", parent.display()),
+        ));
+    }
+
+    File::create(path).map_err(|e| match e.kind() {
+        ErrorKind::PermissionDenied => CodedError::new(
+            ErrorCode::PermissionDenied,
+            format!("Permission denied: {}", path),
+        ),
+        _ => CodedError::new(ErrorCode::Io, format!("Couldn't create the file: {}", e)),
+    })
+}
+
+/// Relative paths are refused, since they depend on where the backend was started
+/// The error says what the path would have meant, so the user can see what went wrong
+fn require_absolute(path: &str) -> Result<(), CodedError> {
+    if Path::new(path).is_absolute() {
+        return Ok(());
+    }
+
+    // canonicalize also resolves symlinks and `..`, but only works on existing paths
+    let resolved = fs::canonicalize(path).or_else(|_| std::path::absolute(path));
+    let message = match resolved {
+        Ok(resolved) => format!(
+            "Path must be absolute: {} (resolved from the backend's working directory: {})",
+            path,
+            resolved.display()
+        ),
+        Err(_) => format!("Path must be absolute: {}", path),
+    };
+    Err(CodedError::new(ErrorCode::InvalidPath, message))
+}
+
+/// A failed write to a file created by create_checked_output
+pub fn write_error(error: io::Error) -> CodedError {
+    CodedError::new(ErrorCode::Io, format!("Couldn't write the file: {}", error))
+}
+
+fn describe_io_error(path: &str, error: &io::Error) -> CodedError {
+    match error.kind() {
+        ErrorKind::NotFound => {
+            CodedError::new(ErrorCode::FileNotFound, format!("File not found: {}", path))
+        }
+        ErrorKind::PermissionDenied => CodedError::new(
+            ErrorCode::PermissionDenied,
+            format!("Permission denied: {}", path),
+        ),
+        ErrorKind::IsADirectory => CodedError::new(
+            ErrorCode::InvalidPath,
+            format!("Path is a directory: {}", path),
+        ),
+        _ => CodedError::new(ErrorCode::Io, format!("Couldn't open the file: {}", error)),
+    }
+}
@@ -1,18 +1,58 @@
-use encoding_rs::Encoding;
+use encoding_rs::{DecoderResult, Encoding, UTF_8};
 use memchr::memchr_iter;
 use std::fs;
+use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
-use std::{fs::File, path::Path};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use crate::Response;
-use crate::services::commands;
+use crate::services::line_cache::{BLOCK_LINES, CachedBlock, DEFAULT_CACHE_BYTES, LineCache};
+use crate::services::line_index::LineIndex;
+use crate::services::{self, Compression, commands};
+use crate::types::{CodedError, DecodePolicy, ErrorCode};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileChangeType {
-    Truncated,
+    /// The file shrank or was replaced, with messages about its new content for the client
+    /// (encoding warnings, a changed encoding)
+    Truncated(Vec<String>),
     LinesAdded,
 }
 
+/// Lines longer than this are clipped when read (1MB)
+pub const DEFAULT_MAX_LINE_BYTES: u64 = 1024 * 1024;
+
+/// Files larger than this are refused unless OpenFile sets allow_large (16GB)
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Bytes read from the start of a refused file to estimate its line count
+const LINE_ESTIMATE_SAMPLE: usize = 64 * 1024;
+
+/// Bytes read at a time when indexing (64KB)
+const SCAN_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Bytes read from the end of a file opened with new_tail to find its last complete line
+const TAIL_WINDOW_BYTES: u64 = 64 * 1024;
+
+/// Bytes scanned at a time when looking for the next line start (window edges)
+const LINE_SEARCH_BYTES: u64 = 64 * 1024;
+
+/// Appended to lines clipped to max_line_bytes (and columns cut by GetChunk's max_column_bytes)
+pub const CLIPPED_MARKER: char = '…';
+
+/// How the encoding of a file is found when it's opened (and after a truncation)
+#[derive(Debug, Clone)]
+pub enum EncodingSource {
+    /// Detected by chardet from a sample of this many bytes, 64KB by default
+    Detect(Option<usize>),
+    /// Given by the client, nothing is sampled
+    Assume(String),
+}
+
+/// Change type, old line count, new line count and the newly added lines
+pub type FileChange = (FileChangeType, u64, u64, Vec<String>);
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum EncodingMode {
     AsciiCompatible, // UTF-8, Latin1, ASCII, etc.
@@ -20,100 +60,602 @@ enum EncodingMode {
     Utf16BE,         // \n is 0x00 0x0A
 }
 
+/// The first line where a new scan of the file disagrees with the index
+/// A newline is None when that side has no such line
+pub struct IndexDivergence {
+    pub line: u64,
+    pub indexed_newline: Option<u64>,
+    pub scanned_newline: Option<u64>,
+}
+
 pub struct FileProcessor {
     pub file_path: String,
-    pub index: Vec<u64>,
+    /// Newline offsets, translated to line bounds by line_start_byte and line_end_byte
+    pub index: LineIndex,
+    /// Byte where line 0 starts, after the history skipped by new_tail or new_window (0 otherwise)
+    pub base_offset: u64,
+    /// Byte where the indexed region of a new_window ends (None: the end of the file)
+    pub end_offset: Option<u64>,
     pub last_file_size: u64,
-    mode: EncodingMode, // Cached mode to avoid string checks in loops
+    pub encoding_label: String,
+    pub max_line_bytes: u64,
+    pub decode_policy: DecodePolicy,
+    /// Set when file_path is a temporary decompressed copy of a compressed file
+    pub compression: Option<Compression>,
+    mode: EncodingMode,              // Cached mode to avoid string checks in loops
+    encoding_source: EncodingSource, // How the encoding was found, kept for reindexing
+    record_separator: u8,            // Byte ending each line, \n unless OpenFile set another one
+    strip_ansi: bool,                // Remove ANSI escape sequences from parsed lines
+    /// Problems found while opening the file that didn't stop it, sent with FileOpened
+    pub warnings: Vec<String>,
+    hold_file: bool,
+    held_file: Option<File>, // Read handle kept between refreshes when hold_file is set
+    cache: Mutex<LineCache>, // Recently read lines, cleared whenever the index is rebuilt
+    scan_buffer: Vec<u8>,    // Reused by every scan, so tailing doesn't allocate on each tick
 }
 
 impl FileProcessor {
-    pub fn new(file_path: &str) -> Result<Self, String> {
-        let path = Path::new(&file_path);
-        if !path.is_absolute() {
-            return Err("Path must be absolute".to_string());
+    /// Open and index a file, compressed files are decompressed to a temporary file first
+    /// Indexing stops early with an error when `cancelled` is set
+    /// Files (or decompressed copies) larger than `size_limit` are refused before indexing
+    /// Lines end with `record_separator`, which can only differ from \n in ASCII-compatible files
+    pub fn new(
+        file_path: &str,
+        encoding_source: EncodingSource,
+        size_limit: Option<u64>,
+        record_separator: u8,
+        cancelled: &AtomicBool,
+    ) -> Result<Self, CodedError> {
+        let mut file = services::open_checked(file_path)?;
+
+        let compression = services::detect_compression(&mut file).map_err(|e| {
+            CodedError::new(ErrorCode::Io, format!("couldn't read the file: {}", e))
+        })?;
+
+        let Some(compression) = compression.filter(|compression| compression.can_decompress())
+        else {
+            let mut processor = Self::index_file(
+                file,
+                file_path,
+                None,
+                encoding_source,
+                size_limit,
+                record_separator,
+                cancelled,
+            )?;
+            if let Some(compression) = compression {
+                processor.warnings.push(format!(
+                    "{} files aren't decompressed yet, the file will be read as-is",
+                    compression.name()
+                ));
+            }
+            return Ok(processor);
+        };
+
+        let decompressed_path = services::decompress_to_temp(file, compression, cancelled)
+            .map_err(|message| CodedError::new(ErrorCode::Io, message))?;
+        let decompressed_path = decompressed_path.to_string_lossy().into_owned();
+        let processor = File::open(&decompressed_path)
+            .map_err(|e| {
+                CodedError::new(
+                    ErrorCode::Io,
+                    format!("couldn't open the decompressed file: {}", e),
+                )
+            })
+            .and_then(|file| {
+                Self::index_file(
+                    file,
+                    &decompressed_path,
+                    Some(compression),
+                    encoding_source,
+                    size_limit,
+                    record_separator,
+                    cancelled,
+                )
+            });
+        if processor.is_err() {
+            let _ = fs::remove_file(&decompressed_path);
+        }
+        processor
+    }
+
+    fn index_file(
+        mut file: File,
+        file_path: &str,
+        compression: Option<Compression>,
+        encoding_source: EncodingSource,
+        size_limit: Option<u64>,
+        record_separator: u8,
+        cancelled: &AtomicBool,
+    ) -> Result<Self, CodedError> {
+        if let Some(size_limit) = size_limit {
+            Self::check_size(&mut file, size_limit, record_separator)?;
+        }
+
+        let (encoding_label, warnings) = Self::detect_encoding_label(file_path, &encoding_source)?;
+
+        // Resolve Encoding and determine Mode
+        let mode = Self::resolve_mode(&encoding_label)?;
+        Self::check_record_separator(record_separator, mode)?;
+
+        let mut index = LineIndex::new();
+        let mut scan_buffer = Vec::new();
+
+        // Pass the determined mode to the indexer
+        FileProcessor::scan_file(
+            &mut file,
+            &mut index,
+            &mut scan_buffer,
+            0,
+            mode,
+            record_separator,
+            Some(cancelled),
+            None,
+        )
+        .map_err(|e| CodedError::new(ErrorCode::Io, format!("couldn't scan the file: {}", e)))?;
+
+        Ok(Self {
+            file_path: String::from(file_path),
+            index,
+            base_offset: 0,
+            end_offset: None,
+            encoding_label,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            decode_policy: DecodePolicy::default(),
+            compression,
+            last_file_size: fs::metadata(file_path)
+                .map_err(|e| {
+                    CodedError::new(
+                        ErrorCode::Io,
+                        format!("couldn't get metadata of file: {}", e),
+                    )
+                })?
+                .len(),
+            mode,
+            encoding_source,
+            record_separator,
+            strip_ansi: false,
+            warnings,
+            hold_file: false,
+            held_file: None,
+            cache: Mutex::new(LineCache::new(DEFAULT_CACHE_BYTES)),
+            scan_buffer,
+        })
+    }
+
+    /// Open a file without indexing what it already contains: line 0 is the first line
+    /// completed after this call, and the file is only indexed as it grows
+    /// A line still being written at the end of the file becomes line 0 once it's complete
+    pub fn new_tail(
+        file_path: &str,
+        encoding_source: EncodingSource,
+        record_separator: u8,
+    ) -> Result<Self, CodedError> {
+        let mut file = services::open_checked(file_path)?;
+
+        let compressed = services::detect_compression(&mut file)
+            .map_err(|e| CodedError::new(ErrorCode::Io, format!("couldn't read the file: {}", e)))?
+            .is_some_and(|compression| compression.can_decompress());
+        if compressed {
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                "A compressed file never grows, open it with OpenFile instead",
+            ));
+        }
+
+        let (encoding_label, warnings) = Self::detect_encoding_label(file_path, &encoding_source)?;
+        let mode = Self::resolve_mode(&encoding_label)?;
+        Self::check_record_separator(record_separator, mode)?;
+
+        let mut processor = Self {
+            file_path: String::from(file_path),
+            index: LineIndex::new(),
+            base_offset: 0,
+            end_offset: None,
+            encoding_label,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            decode_policy: DecodePolicy::default(),
+            compression: None,
+            last_file_size: 0,
+            mode,
+            encoding_source,
+            record_separator,
+            strip_ansi: false,
+            warnings,
+            hold_file: false,
+            held_file: None,
+            cache: Mutex::new(LineCache::new(DEFAULT_CACHE_BYTES)),
+            scan_buffer: Vec::new(),
+        };
+        processor.skip_history().map_err(|e| {
+            CodedError::new(ErrorCode::Io, format!("couldn't scan the file: {}", e))
+        })?;
+
+        Ok(processor)
+    }
+
+    /// Open a file and only index the lines starting in [start_byte, end_byte)
+    /// A line cut by start_byte belongs to the previous window and one cut by end_byte is
+    /// kept whole, so consecutive windows cover every line exactly once
+    /// Windows larger than `size_limit` are refused before indexing
+    pub fn new_window(
+        file_path: &str,
+        encoding_source: EncodingSource,
+        record_separator: u8,
+        (start_byte, end_byte): (u64, u64),
+        size_limit: Option<u64>,
+        cancelled: &AtomicBool,
+    ) -> Result<Self, CodedError> {
+        let mut file = services::open_checked(file_path)?;
+        let io_error = |e: std::io::Error| {
+            CodedError::new(ErrorCode::Io, format!("couldn't scan the file: {}", e))
+        };
+
+        let compressed = services::detect_compression(&mut file)
+            .map_err(|e| CodedError::new(ErrorCode::Io, format!("couldn't read the file: {}", e)))?
+            .is_some_and(|compression| compression.can_decompress());
+        if compressed {
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                "A byte range of a compressed file can't be read directly, open it with OpenFile instead",
+            ));
+        }
+
+        if start_byte >= end_byte {
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Invalid window: start_byte ({}) must be lower than end_byte ({})",
+                    start_byte, end_byte
+                ),
+            ));
+        }
+        let file_size = file.metadata().map_err(io_error)?.len();
+        if start_byte >= file_size {
+            return Err(CodedError::new(
+                ErrorCode::OutOfBounds,
+                format!(
+                    "start_byte ({}) out of bounds (file has {} bytes)",
+                    start_byte, file_size
+                ),
+            ));
+        }
+        let end_byte = end_byte.min(file_size);
+        if let Some(size_limit) = size_limit
+            && end_byte - start_byte > size_limit
+        {
+            return Err(CodedError::new(
+                ErrorCode::FileTooLarge,
+                format!(
+                    "Window is {} bytes, over the limit of {} bytes, open it with allow_large to proceed",
+                    end_byte - start_byte,
+                    size_limit
+                ),
+            ));
+        }
+
+        let (encoding_label, warnings) = Self::detect_encoding_label(file_path, &encoding_source)?;
+        let mode = Self::resolve_mode(&encoding_label)?;
+        Self::check_record_separator(record_separator, mode)?;
+
+        let mut processor = Self {
+            file_path: String::from(file_path),
+            index: LineIndex::new(),
+            base_offset: 0,
+            end_offset: None,
+            encoding_label,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            decode_policy: DecodePolicy::default(),
+            compression: None,
+            last_file_size: 0,
+            mode,
+            encoding_source,
+            record_separator,
+            strip_ansi: false,
+            warnings,
+            hold_file: false,
+            held_file: None,
+            cache: Mutex::new(LineCache::new(DEFAULT_CACHE_BYTES)),
+            scan_buffer: Vec::new(),
+        };
+
+        // Snap both edges to line starts, without a window start nothing starts in the window
+        processor.base_offset = match start_byte {
+            0 => 0,
+            _ => processor
+                .next_line_start(&mut file, start_byte)
+                .map_err(io_error)?
+                .unwrap_or(file_size),
+        };
+        let end_offset = if processor.base_offset >= end_byte {
+            processor.base_offset
+        } else {
+            processor
+                .next_line_start(&mut file, end_byte)
+                .map_err(io_error)?
+                .unwrap_or(file_size)
+        };
+        processor.end_offset = Some(end_offset);
+
+        file.seek(SeekFrom::Start(processor.base_offset))
+            .map_err(io_error)?;
+        processor.last_file_size = Self::scan_file(
+            &mut file,
+            &mut processor.index,
+            &mut processor.scan_buffer,
+            processor.base_offset,
+            mode,
+            record_separator,
+            Some(cancelled),
+            Some(end_offset),
+        )
+        .map_err(io_error)?;
+
+        Ok(processor)
+    }
+
+    /// The first line start at or after `from` (the byte after a newline)
+    /// Returns None when no newline ends there, i.e. `from` is in the last line
+    fn next_line_start(&self, file: &mut File, from: u64) -> std::io::Result<Option<u64>> {
+        // Start early enough to see a newline ending right at `from`, on a character boundary
+        let mut search_start = from.saturating_sub(self.newline_size());
+        if self.mode != EncodingMode::AsciiCompatible {
+            search_start -= search_start % 2;
+        }
+
+        loop {
+            file.seek(SeekFrom::Start(search_start))?;
+            let search_end = search_start + LINE_SEARCH_BYTES;
+            let mut newlines = LineIndex::new();
+            let scanned_to = Self::scan_file(
+                file,
+                &mut newlines,
+                &mut Vec::new(),
+                search_start,
+                self.mode,
+                self.record_separator,
+                None,
+                Some(search_end),
+            )?;
+
+            let line_start = newlines
+                .iter()
+                .map(|newline| newline + self.newline_last_byte_offset() + 1)
+                .find(|&line_start| line_start >= from);
+            if line_start.is_some() || scanned_to < search_end {
+                return Ok(line_start);
+            }
+
+            // Overlap by a newline so one split between two reads isn't missed
+            search_start = scanned_to - self.newline_size();
+        }
+    }
+
+    /// Move base_offset right after the last newline of the file
+    /// Only the end of the file is scanned; if it holds no newline, the whole file is skipped
+    fn skip_history(&mut self) -> std::io::Result<()> {
+        let mut file = File::open(&self.file_path)?;
+        let file_size = file.metadata()?.len();
+
+        let mut window_start = file_size.saturating_sub(TAIL_WINDOW_BYTES);
+        if self.mode != EncodingMode::AsciiCompatible {
+            window_start -= window_start % 2;
+        }
+        file.seek(SeekFrom::Start(window_start))?;
+
+        let mut newlines = LineIndex::new();
+        self.last_file_size = Self::scan_file(
+            &mut file,
+            &mut newlines,
+            &mut self.scan_buffer,
+            window_start,
+            self.mode,
+            self.record_separator,
+            None,
+            None,
+        )?;
+
+        self.base_offset = newlines.last().map_or(self.last_file_size, |newline| {
+            newline + self.newline_last_byte_offset() + 1
+        });
+        Ok(())
+    }
+
+    /// Refuse a file over size_limit: its index (4 bytes per line) could exhaust memory
+    /// The error gives an estimate of that memory, from the line density of the file's start
+    fn check_size(
+        file: &mut File,
+        size_limit: u64,
+        record_separator: u8,
+    ) -> Result<(), CodedError> {
+        let io_error = |e: std::io::Error| {
+            CodedError::new(ErrorCode::Io, format!("couldn't read the file: {}", e))
+        };
+
+        let file_size = file.metadata().map_err(io_error)?.len();
+        if file_size <= size_limit {
+            return Ok(());
         }
 
+        let mut sample = Vec::with_capacity(LINE_ESTIMATE_SAMPLE);
+        file.by_ref()
+            .take(LINE_ESTIMATE_SAMPLE as u64)
+            .read_to_end(&mut sample)
+            .map_err(io_error)?;
+        file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+
+        let newlines = memchr_iter(record_separator, &sample).count().max(1) as u64;
+        let estimated_lines =
+            (file_size as u128 * newlines as u128 / sample.len().max(1) as u128) as u64;
+        let index_bytes = estimated_lines.saturating_mul(std::mem::size_of::<u32>() as u64);
+
+        Err(CodedError::new(
+            ErrorCode::FileTooLarge,
+            format!(
+                "File is {} bytes, over the limit of {} bytes. Its index would take about {} bytes of memory ({} lines), open it with allow_large to proceed",
+                file_size, size_limit, index_bytes, estimated_lines
+            ),
+        ))
+    }
+
+    /// Detect the file's encoding, falling back to utf-8 when it isn't supported
+    /// Returns the label and a warning for each fallback
+    /// An assumed encoding is returned as is, it's checked when resolving its mode
+    fn detect_encoding_label(
+        file_path: &str,
+        encoding_source: &EncodingSource,
+    ) -> Result<(String, Vec<String>), CodedError> {
+        let detect_bytes = match encoding_source {
+            EncodingSource::Detect(detect_bytes) => *detect_bytes,
+            EncodingSource::Assume(label) => return Ok((label.clone(), Vec::new())),
+        };
+
         // Get file encoding support
-        let encoding = commands::get_file_encoding(file_path);
+        let encoding = commands::get_file_encoding(file_path, detect_bytes);
 
         let (mut encoding_label, is_supported) = match encoding {
             Response::Encoding {
                 encoding,
                 is_supported,
             } => (encoding, is_supported),
-            Response::Error { message } => {
-                return Err(format!("Failed to get file encoding: {}", message));
+            Response::Error { message, code } => {
+                return Err(CodedError::new(
+                    code.unwrap_or(ErrorCode::Io),
+                    format!("Failed to get file encoding: {}", message),
+                ));
             }
             _ => {
-                return Err(String::from("Unexpected response from get_file_encoding"));
+                return Err(CodedError::new(
+                    ErrorCode::Internal,
+                    "Unexpected response from get_file_encoding",
+                ));
             }
         };
 
         // Check if encoding is supported
+        let mut warnings = Vec::new();
         if !is_supported {
-            let response = Response::Info {
-                message:
-                    "encoding is not supported, file will be treated as if it has utf8 encoding"
-                        .to_string(),
-            };
-            println!("{}", serde_json::to_string(&response).unwrap());
+            warnings.push(format!(
+                "encoding {} is not supported, file will be treated as if it has utf8 encoding",
+                encoding_label
+            ));
             encoding_label = String::from("utf-8");
         }
 
-        // Resolve Encoding and determine Mode
+        Ok((encoding_label, warnings))
+    }
+
+    /// Resolve an encoding label to the mode used by the indexer
+    fn resolve_mode(encoding_label: &str) -> Result<EncodingMode, CodedError> {
         let encoding = match Encoding::for_label(encoding_label.as_bytes()) {
             Some(enc) => enc,
             None => {
-                return Err(format!("Unknown encoding label: {}", encoding_label));
+                return Err(CodedError::new(
+                    ErrorCode::UnsupportedEncoding,
+                    format!("Unknown encoding label: {}", encoding_label),
+                ));
             }
         };
 
-        let mode = if encoding.is_ascii_compatible() {
-            EncodingMode::AsciiCompatible
+        if encoding.is_ascii_compatible() {
+            Ok(EncodingMode::AsciiCompatible)
         } else if encoding_label.eq_ignore_ascii_case("utf-16le") {
-            EncodingMode::Utf16LE
+            Ok(EncodingMode::Utf16LE)
         } else if encoding_label.eq_ignore_ascii_case("utf-16be") {
-            EncodingMode::Utf16BE
+            Ok(EncodingMode::Utf16BE)
         } else if encoding_label.eq_ignore_ascii_case("utf-16") {
             // Default to UTF-16LE if just "UTF-16" is detected
-            EncodingMode::Utf16LE
+            Ok(EncodingMode::Utf16LE)
         } else {
-            return Err(format!("Unsupported file encoding: {}", encoding_label));
-        };
+            Err(CodedError::new(
+                ErrorCode::UnsupportedEncoding,
+                format!("Unsupported file encoding: {}", encoding_label),
+            ))
+        }
+    }
 
-        let mut file = File::open(file_path).map_err(|e| format!("couldn't open file: {}", e))?;
-        let mut index: Vec<u64> = Vec::new();
+    /// A custom record separator must be an ASCII byte, so it can't be part of a
+    /// multibyte character, and isn't supported in UTF-16 files yet
+    fn check_record_separator(record_separator: u8, mode: EncodingMode) -> Result<(), CodedError> {
+        if record_separator == b'\n' {
+            return Ok(());
+        }
 
-        // Pass the determined mode to the indexer
-        FileProcessor::scan_file(&mut file, &mut index, 0, mode)
-            .map_err(|e| format!("couldn't scan the file: {}", e))?;
+        if !record_separator.is_ascii() {
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "record_separator must be an ASCII byte (0-127), got {}",
+                    record_separator
+                ),
+            ));
+        }
 
-        Ok(Self {
-            file_path: String::from(file_path),
-            index,
-            last_file_size: fs::metadata(file_path)
-                .map_err(|e| format!("couldn't get metadata of file: {}", e))?
-                .len(),
-            mode,
-        })
+        if mode != EncodingMode::AsciiCompatible {
+            return Err(CodedError::new(
+                ErrorCode::UnsupportedEncoding,
+                "A custom record_separator is only supported for ASCII-compatible encodings",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Force a different encoding (e.g. when chardet guessed wrong) and rebuild the index
+    pub fn set_encoding(&mut self, encoding_label: &str) -> Result<(), CodedError> {
+        let mode = Self::resolve_mode(encoding_label)?;
+        Self::check_record_separator(self.record_separator, mode)?;
+        self.mode = mode;
+        self.encoding_label = encoding_label.to_string();
+
+        self.full_reindex()
+            .map_err(|e| CodedError::new(ErrorCode::Io, format!("Failed to reindex file: {}", e)))
     }
 
     /// Core scanning logic extracted to handle both initial and incremental indexing
+    /// Reading stops at end_offset when it's set, the returned offset is where it stopped
+    /// `buffer` is grown to SCAN_BUFFER_BYTES on first use and kept for the next scans
+    #[allow(clippy::too_many_arguments)]
     fn scan_file(
         file: &mut File,
-        index: &mut Vec<u64>,
+        index: &mut LineIndex,
+        buffer: &mut Vec<u8>,
         start_offset: u64,
         mode: EncodingMode,
+        record_separator: u8,
+        cancelled: Option<&AtomicBool>,
+        end_offset: Option<u64>,
     ) -> std::io::Result<u64> {
-        let mut buffer = [0u8; 64 * 1024]; // 64KB
+        if buffer.len() < SCAN_BUFFER_BYTES {
+            buffer.resize(SCAN_BUFFER_BYTES, 0);
+        }
         let mut total_offset = start_offset;
 
         // State for carrying boundary bytes between chunks (crucial for UTF-16 split across buffers)
         let mut last_byte_of_prev_chunk: Option<u8> = None;
 
         loop {
-            let bytes_read = file.read(&mut buffer)?;
+            if cancelled.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "indexing was cancelled",
+                ));
+            }
+
+            let to_read = end_offset.map_or(buffer.len(), |end| {
+                end.saturating_sub(total_offset).min(buffer.len() as u64) as usize
+            });
+            if to_read == 0 {
+                break;
+            }
+
+            // Keep the position in the error, to tell where flaky storage gave up
+            let bytes_read = file.read(&mut buffer[..to_read]).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("read failed at byte {}: {}", total_offset, e),
+                )
+            })?;
             if bytes_read == 0 {
                 break;
             }
@@ -123,7 +665,7 @@ impl FileProcessor {
             match mode {
                 EncodingMode::AsciiCompatible => {
                     // Original extremely fast logic
-                    for pos in memchr_iter(b'\n', chunk) {
+                    for pos in memchr_iter(record_separator, chunk) {
                         index.push(total_offset + pos as u64);
                     }
                 }
@@ -198,18 +740,51 @@ impl FileProcessor {
         Ok(total_offset)
     }
 
-    pub fn refresh_if_needed(&mut self) -> Result<Option<(FileChangeType, u64, u64, Vec<String>)>, String> {
+    /// Keep a read handle open between refreshes instead of reopening the file on every change
+    /// If the held handle fails, the file is reopened
+    pub fn hold_file_handle(&mut self) {
+        self.hold_file = true;
+        self.held_file = File::open(&self.file_path).ok();
+    }
+
+    /// Whether the held handle points to another file than file_path, e.g. after a rotation
+    #[cfg(unix)]
+    fn held_file_replaced(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let Some(held_file) = &self.held_file else {
+            return false;
+        };
+
+        match (held_file.metadata(), fs::metadata(&self.file_path)) {
+            (Ok(held), Ok(current)) => held.dev() != current.dev() || held.ino() != current.ino(),
+            _ => false,
+        }
+    }
+
+    /// The standard library has no stable file identity on other platforms
+    #[cfg(not(unix))]
+    fn held_file_replaced(&self) -> bool {
+        false
+    }
+
+    pub fn refresh_if_needed(&mut self) -> Result<Option<FileChange>, String> {
         let current_size = std::fs::metadata(&self.file_path)
             .map_err(|e| format!("Failed to get file metadata: {}", e))?
             .len();
 
-        if current_size < self.last_file_size {
+        // A replaced file (rotation) is handled like a truncation
+        if self.held_file_replaced() || current_size < self.last_file_size {
             let old_line_count = self.index.len() as u64;
-            self.full_reindex()
-                .map_err(|e| format!("Failed to reindex file: {}", e))?;
+            let messages = self.reindex_after_truncation()?;
             let new_line_count = self.index.len() as u64;
             // File was truncated, no new lines to return
-            Ok(Some((FileChangeType::Truncated, old_line_count, new_line_count, Vec::new())))
+            Ok(Some((
+                FileChangeType::Truncated(messages),
+                old_line_count,
+                new_line_count,
+                Vec::new(),
+            )))
         } else if current_size > self.last_file_size {
             let old_line_count = self.index.len() as u64;
             self.incremental_index()
@@ -223,25 +798,131 @@ impl FileProcessor {
                 Vec::new()
             };
 
-            Ok(Some((FileChangeType::LinesAdded, old_line_count, new_line_count, new_lines)))
+            Ok(Some((
+                FileChangeType::LinesAdded,
+                old_line_count,
+                new_line_count,
+                new_lines,
+            )))
         } else {
             Ok(None)
         }
     }
 
+    /// A truncated file may have been rotated and replaced by a file with a different
+    /// encoding, so the encoding is detected again before rebuilding the index
+    /// Returns the encoding warnings and changes, the watcher sends them with the truncation
+    fn reindex_after_truncation(&mut self) -> Result<Vec<String>, String> {
+        // The skipped history is gone, everything in the new content is new
+        self.base_offset = 0;
+
+        let (encoding_label, mut messages) =
+            Self::detect_encoding_label(&self.file_path, &self.encoding_source)?;
+
+        // Lines are decoded with the label's encoding, not only split according to its mode
+        if Encoding::for_label(encoding_label.as_bytes())
+            != Encoding::for_label(self.encoding_label.as_bytes())
+        {
+            messages.push(format!(
+                "encoding changed from {} to {} after the file was truncated, reindexing with the new encoding",
+                self.encoding_label, encoding_label
+            ));
+            self.set_encoding(&encoding_label)?;
+            return Ok(messages);
+        }
+
+        self.full_reindex()
+            .map_err(|e| format!("Failed to reindex file: {}", e))?;
+        Ok(messages)
+    }
+
+    /// Rebuild the index from scratch, even if the size didn't change (e.g. in-place edits)
+    pub fn reindex(&mut self) -> Result<(), CodedError> {
+        self.full_reindex()
+            .map_err(|e| CodedError::new(ErrorCode::Io, format!("Failed to reindex file: {}", e)))
+    }
+
+    /// Scan the indexed region again into a new index and compare it with the live one
+    /// Only the bytes indexed so far are scanned, lines appended since aren't a difference
+    /// Returns the number of lines found by the scan, and where it first disagrees (None: it doesn't)
+    pub fn verify_index(&self) -> std::io::Result<(u64, Option<IndexDivergence>)> {
+        let mut file = File::open(&self.file_path)?;
+        file.seek(SeekFrom::Start(self.base_offset))?;
+
+        let mut scanned = LineIndex::new();
+        Self::scan_file(
+            &mut file,
+            &mut scanned,
+            &mut Vec::new(),
+            self.base_offset,
+            self.mode,
+            self.record_separator,
+            None,
+            Some(self.end_offset.unwrap_or(self.last_file_size)),
+        )?;
+
+        let line_at =
+            |index: &LineIndex, line: usize| (line < index.len()).then(|| index.get(line));
+        let divergence = (0..self.index.len().max(scanned.len()))
+            .map(|line| (line, line_at(&self.index, line), line_at(&scanned, line)))
+            .find(|(_, indexed, found)| indexed != found)
+            .map(|(line, indexed_newline, scanned_newline)| IndexDivergence {
+                line: line as u64,
+                indexed_newline,
+                scanned_newline,
+            });
+
+        Ok((scanned.len() as u64, divergence))
+    }
+
     fn full_reindex(&mut self) -> std::io::Result<()> {
         self.index.clear();
+        self.clear_cache();
+
+        // Always reopen, the held handle may point to a file that was rotated away
         let mut file = File::open(&self.file_path)?;
 
-        let new_size = Self::scan_file(&mut file, &mut self.index, 0, self.mode)?;
+        // Keep skipping the history of a tailed file, unless it shrank below it
+        if file.metadata()?.len() < self.base_offset {
+            self.base_offset = 0;
+        }
+        if self.mode != EncodingMode::AsciiCompatible {
+            self.base_offset -= self.base_offset % 2;
+        }
+        file.seek(SeekFrom::Start(self.base_offset))?;
+
+        let new_size = Self::scan_file(
+            &mut file,
+            &mut self.index,
+            &mut self.scan_buffer,
+            self.base_offset,
+            self.mode,
+            self.record_separator,
+            None,
+            self.end_offset,
+        )?;
 
         self.last_file_size = new_size;
+        self.held_file = self.hold_file.then_some(file);
         Ok(())
     }
 
     fn incremental_index(&mut self) -> std::io::Result<()> {
-        let mut file = File::open(&self.file_path)?;
+        let indexed_lines = self.index.len();
+
+        if let Some(file) = self.held_file.take() {
+            match self.index_from_last_size(file) {
+                Ok(()) => return Ok(()),
+                // Drop what the failing handle indexed and fall back to reopening the file
+                Err(_) => self.index.truncate(indexed_lines),
+            }
+        }
+
+        let file = File::open(&self.file_path)?;
+        self.index_from_last_size(file)
+    }
 
+    fn index_from_last_size(&mut self, mut file: File) -> std::io::Result<()> {
         // For UTF-16, we must be careful not to start reading in the middle of a character pair.
         // If last_file_size is odd (which shouldn't happen in valid UTF-16), we align it.
         let mut start_pos = self.last_file_size;
@@ -257,103 +938,798 @@ impl FileProcessor {
 
         // For incremental, we need to populate index only with new items,
         // but we assume `scan_file` appends to the provided vector.
-        let new_size = Self::scan_file(&mut file, &mut self.index, start_pos, self.mode)?;
+        let new_size = Self::scan_file(
+            &mut file,
+            &mut self.index,
+            &mut self.scan_buffer,
+            start_pos,
+            self.mode,
+            self.record_separator,
+            None,
+            None,
+        )?;
 
         self.last_file_size = new_size;
+        self.held_file = self.hold_file.then_some(file);
         Ok(())
     }
 
+    /// Determine newline size based on encoding mode
+    /// UTF-16LE: newline is 0x0A 0x00 (2 bytes)
+    /// UTF-16BE: newline is 0x00 0x0A (2 bytes)
+    /// ASCII-compatible: newline is 0x0A (1 byte)
+    pub fn newline_size(&self) -> u64 {
+        match self.mode {
+            EncodingMode::Utf16LE | EncodingMode::Utf16BE => 2,
+            EncodingMode::AsciiCompatible => 1,
+        }
+    }
+
+    /// The encoding an ASCII-compatible file is decoded with: UTF-8, or the legacy encoding
+    /// its label names (Latin1, windows-1252, Shift_JIS...)
+    fn ascii_compatible_encoding(&self) -> &'static Encoding {
+        Encoding::for_label(self.encoding_label.as_bytes()).unwrap_or(UTF_8)
+    }
+
+    /// The file's encoding when it's UTF-16, None for ASCII-compatible files
+    pub fn utf16_encoding(&self) -> Option<&'static Encoding> {
+        match self.mode {
+            EncodingMode::Utf16LE => Some(encoding_rs::UTF_16LE),
+            EncodingMode::Utf16BE => Some(encoding_rs::UTF_16BE),
+            EncodingMode::AsciiCompatible => None,
+        }
+    }
+
+    /// Distance from an indexed position to the last byte of its newline
+    /// The index points at the 0x0A byte; only in UTF-16LE is it followed by another newline byte
+    fn newline_last_byte_offset(&self) -> u64 {
+        match self.mode {
+            EncodingMode::Utf16LE => 1,
+            EncodingMode::Utf16BE | EncodingMode::AsciiCompatible => 0,
+        }
+    }
+
+    /// Whether the file ends with a newline (false for an empty file)
+    /// Without lines, a tailed file ends with a newline if its skipped history does
+    pub fn has_trailing_newline(&self) -> bool {
+        let end_of_lines = match self.index.last() {
+            Some(newline) => newline + self.newline_last_byte_offset() + 1,
+            None => self.base_offset,
+        };
+        end_of_lines > 0 && end_of_lines == self.last_file_size
+    }
+
+    /// Find the line containing the given byte offset
+    /// A newline's bytes belong to the line it terminates
+    /// Returns None when the offset is past the last indexed newline
+    pub fn line_at_byte(&self, byte: u64) -> Option<u64> {
+        let last_byte_offset = self.newline_last_byte_offset();
+
+        let line = self
+            .index
+            .partition_point(|newline| newline + last_byte_offset < byte);
+
+        if line < self.index.len() {
+            Some(line as u64)
+        } else {
+            None
+        }
+    }
+
     /// Read lines from start_line to end_line (inclusive) and decode them properly
-    pub fn read_lines_range(&self, start_line: u64, end_line: u64) -> Result<Vec<String>, String> {
+    pub fn read_lines_range(
+        &self,
+        start_line: u64,
+        end_line: u64,
+    ) -> Result<Vec<String>, CodedError> {
+        self.read_lines_range_clipped(start_line, end_line)
+            .map(|(lines, _, _)| lines)
+    }
+
+    /// Read a single line, clipped like with read_lines_range
+    /// When its block isn't cached, only this line is read instead of the whole block
+    pub fn read_line(&self, line: u64) -> Result<String, CodedError> {
+        let line_count = self.index.len() as u64;
+        if line >= line_count {
+            return Err(CodedError::new(
+                ErrorCode::OutOfBounds,
+                format!(
+                    "line ({}) out of bounds (file has {} lines)",
+                    line, line_count
+                ),
+            ));
+        }
+
+        if let Some(cached) = self.lock_cache().get(line / BLOCK_LINES)
+            && let Some(text) = cached.lines.get((line % BLOCK_LINES) as usize)
+        {
+            self.check_strict(
+                cached
+                    .decode_error_lines
+                    .iter()
+                    .copied()
+                    .filter(|&l| l == line),
+            )?;
+            return Ok(text.clone());
+        }
+
+        let mut file = self.open_for_read()?;
+        if self.line_byte_len(line) > self.max_line_bytes {
+            let (text, had_errors) = self.read_clipped_line(&mut file, line)?;
+            self.check_strict(had_errors.then_some(line).into_iter())?;
+            return Ok(text);
+        }
+
+        let (mut lines, invalid_lines) = self.read_run(&mut file, line, line)?;
+        self.check_strict(invalid_lines.into_iter())?;
+        Ok(lines.pop().unwrap_or_default())
+    }
+
+    /// Same as read_lines_range, but also returns the line numbers that were clipped
+    /// and whether any line had bytes that couldn't be decoded (replaced with �)
+    /// Lines longer than max_line_bytes are cut and end with CLIPPED_MARKER,
+    /// so a single pathological line can't make us allocate gigabytes
+    pub fn read_lines_range_clipped(
+        &self,
+        start_line: u64,
+        end_line: u64,
+    ) -> Result<(Vec<String>, Vec<u64>, bool), CodedError> {
+        self.read_lines_cached(start_line, end_line, true)
+    }
+
+    /// Same as read_lines_range, for scans over the whole file (search, filters)
+    /// Cached blocks are used, but the blocks read are only kept when the whole file fits
+    /// in the cache: a larger scan would evict everything without ever hitting
+    pub fn scan_lines_range(
+        &self,
+        start_line: u64,
+        end_line: u64,
+    ) -> Result<Vec<String>, CodedError> {
+        let decoded_size =
+            self.last_file_size + self.index.len() as u64 * std::mem::size_of::<String>() as u64;
+        let keep_blocks = decoded_size <= DEFAULT_CACHE_BYTES as u64;
+
+        self.read_lines_cached(start_line, end_line, keep_blocks)
+            .map(|(lines, _, _)| lines)
+    }
+
+    /// Read lines block by block, through the cache
+    /// Blocks that weren't cached are added to it when keep_blocks is set
+    fn read_lines_cached(
+        &self,
+        start_line: u64,
+        end_line: u64,
+        keep_blocks: bool,
+    ) -> Result<(Vec<String>, Vec<u64>, bool), CodedError> {
         let line_count = self.index.len() as u64;
 
         if line_count == 0 {
-            return Err(String::from("File is empty"));
+            return Err(CodedError::new(ErrorCode::OutOfBounds, "File is empty"));
         }
 
         if start_line >= line_count {
-            return Err(format!(
-                "start_line ({}) out of bounds (file has {} lines)",
-                start_line, line_count
+            return Err(CodedError::new(
+                ErrorCode::OutOfBounds,
+                format!(
+                    "start_line ({}) out of bounds (file has {} lines)",
+                    start_line, line_count
+                ),
             ));
         }
 
         if start_line > end_line {
-            return Err(format!(
-                "Invalid range: start_line ({}) > end_line ({})",
-                start_line, end_line
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Invalid range: start_line ({}) > end_line ({})",
+                    start_line, end_line
+                ),
             ));
         }
 
         // Clamp end_line to available lines
         let actual_end_line = end_line.min(line_count - 1);
 
-        // Determine newline size based on encoding mode
-        // UTF-16LE: newline is 0x0A 0x00 (2 bytes)
-        // UTF-16BE: newline is 0x00 0x0A (2 bytes)
-        // ASCII-compatible: newline is 0x0A (1 byte)
-        let newline_size = match self.mode {
-            EncodingMode::Utf16LE | EncodingMode::Utf16BE => 2,
-            EncodingMode::AsciiCompatible => 1,
+        // Opened on the first block that isn't cached
+        let mut file = None;
+
+        let mut lines = Vec::with_capacity((actual_end_line - start_line + 1) as usize);
+        let mut clipped_lines = Vec::new();
+        let mut had_decode_errors = false;
+
+        for block in start_line / BLOCK_LINES..=actual_end_line / BLOCK_LINES {
+            let block_start = block * BLOCK_LINES;
+            let cached = self.cached_block(&mut file, block, keep_blocks)?;
+
+            clipped_lines.extend(
+                cached
+                    .clipped_lines
+                    .iter()
+                    .filter(|&&line| (start_line..=actual_end_line).contains(&line)),
+            );
+            let mut invalid_lines = cached
+                .decode_error_lines
+                .iter()
+                .copied()
+                .filter(|line| (start_line..=actual_end_line).contains(line))
+                .peekable();
+            had_decode_errors |= invalid_lines.peek().is_some();
+            self.check_strict(invalid_lines)?;
+
+            let from = start_line.saturating_sub(block_start) as usize;
+            let to = ((actual_end_line - block_start + 1) as usize).min(cached.lines.len());
+            if from >= to {
+                continue;
+            }
+
+            // Lines of a block that isn't kept in the cache are moved instead of copied
+            match Arc::try_unwrap(cached) {
+                Ok(mut block) => lines.extend(block.lines.drain(from..to)),
+                Err(cached) => lines.extend_from_slice(&cached.lines[from..to]),
+            }
+        }
+
+        Ok((lines, clipped_lines, had_decode_errors))
+    }
+
+    /// Decoded lines of a block, from the cache when they're there
+    /// A cached block that has fewer lines than the index now has (the file grew) is read again
+    fn cached_block(
+        &self,
+        file: &mut Option<File>,
+        block: u64,
+        keep_block: bool,
+    ) -> Result<Arc<CachedBlock>, CodedError> {
+        let block_start = block * BLOCK_LINES;
+        let block_end = (block_start + BLOCK_LINES).min(self.index.len() as u64) - 1;
+
+        if let Some(cached) = self.lock_cache().get(block)
+            && cached.lines.len() as u64 == block_end - block_start + 1
+        {
+            return Ok(cached);
+        }
+
+        let file = match file {
+            Some(file) => file,
+            None => file.insert(self.open_for_read()?),
         };
+        let cached = Arc::new(self.read_block(file, block_start, block_end)?);
+        if keep_block {
+            self.lock_cache().insert(block, Arc::clone(&cached));
+        }
+        Ok(cached)
+    }
+
+    /// A poisoned cache is still consistent enough to be used, entries are replaced whole
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, LineCache> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn clear_cache(&mut self) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+
+    /// Read the lines from start_line to end_line (inclusive), clipping long lines
+    fn read_block(
+        &self,
+        file: &mut File,
+        start_line: u64,
+        actual_end_line: u64,
+    ) -> Result<CachedBlock, CodedError> {
+        let mut lines = Vec::new();
+        let mut clipped_lines = Vec::new();
+        let mut decode_error_lines = Vec::new();
+
+        // Consecutive lines that fit are read in a single pass
+        let mut run_start = start_line;
+        for line in start_line..=actual_end_line {
+            if self.line_byte_len(line) <= self.max_line_bytes {
+                continue;
+            }
+
+            if run_start < line {
+                let (run, invalid_lines) = self.read_run(file, run_start, line - 1)?;
+                decode_error_lines.extend(invalid_lines);
+                lines.extend(run);
+            }
+            let (clipped, had_errors) = self.read_clipped_line(file, line)?;
+            if had_errors {
+                decode_error_lines.push(line);
+            }
+            lines.push(clipped);
+            clipped_lines.push(line);
+            run_start = line + 1;
+        }
+
+        if run_start <= actual_end_line {
+            let (run, invalid_lines) = self.read_run(file, run_start, actual_end_line)?;
+            decode_error_lines.extend(invalid_lines);
+            lines.extend(run);
+        }
+
+        Ok(CachedBlock {
+            lines,
+            clipped_lines,
+            decode_error_lines,
+        })
+    }
+
+    /// Read and decode lines from start_line to end_line (inclusive) without clipping long lines
+    pub fn read_lines_range_unclipped(
+        &self,
+        start_line: u64,
+        end_line: u64,
+    ) -> Result<Vec<String>, CodedError> {
+        let line_count = self.index.len() as u64;
+
+        if start_line >= line_count || start_line > end_line {
+            return Err(CodedError::new(
+                ErrorCode::OutOfBounds,
+                format!(
+                    "Invalid range: {}..={} (file has {} lines)",
+                    start_line, end_line, line_count
+                ),
+            ));
+        }
+
+        let mut file = self.open_for_read()?;
+        let (lines, invalid_lines) =
+            self.read_run(&mut file, start_line, end_line.min(line_count - 1))?;
+        self.check_strict(invalid_lines.into_iter())?;
+        Ok(lines)
+    }
+
+    /// Read the exact bytes of the lines from start_line to end_line (inclusive),
+    /// newlines included, without decoding
+    /// At most max_bytes are read, returns the bytes, the byte offset they start at
+    /// and whether the range was cut short
+    pub fn read_raw_range(
+        &self,
+        start_line: u64,
+        end_line: u64,
+        max_bytes: u64,
+    ) -> Result<(Vec<u8>, u64, bool), CodedError> {
+        let line_count = self.index.len() as u64;
+
+        if start_line >= line_count {
+            return Err(CodedError::new(
+                ErrorCode::OutOfBounds,
+                format!(
+                    "start_line ({}) out of bounds (file has {} lines)",
+                    start_line, line_count
+                ),
+            ));
+        }
+
+        if start_line > end_line {
+            return Err(CodedError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Invalid range: start_line ({}) > end_line ({})",
+                    start_line, end_line
+                ),
+            ));
+        }
+
+        let actual_end_line = end_line.min(line_count - 1);
+        let start_pos = self.line_start_byte(start_line);
+        let end_pos = self.line_end_byte(actual_end_line);
+
+        let span = Self::byte_span(start_pos, end_pos)?;
+        let truncated = span > max_bytes;
+        let bytes_to_read = span.min(max_bytes);
+
+        let mut file = self.open_for_read()?;
+        let buffer = Self::read_bytes(&mut file, start_pos, bytes_to_read)?;
+
+        Ok((buffer, start_pos, truncated))
+    }
+
+    /// Byte offset of the first byte of a line, right after the previous newline
+    pub fn line_start_byte(&self, line: u64) -> u64 {
+        match line {
+            0 => self.base_offset,
+            line => self.line_end_byte(line - 1),
+        }
+    }
+
+    /// Byte offset right after the last byte of a line's newline
+    pub fn line_end_byte(&self, line: u64) -> u64 {
+        self.index.get(line as usize) + self.newline_last_byte_offset() + 1
+    }
+
+    /// Length of a line in bytes, without its newline
+    pub fn line_byte_len(&self, line: u64) -> u64 {
+        // In UTF-16BE the indexed 0x0A byte is the second byte of the newline
+        let newline_start = self.index.get(line as usize) + self.newline_last_byte_offset() + 1
+            - self.newline_size();
+        newline_start.saturating_sub(self.line_start_byte(line))
+    }
 
+    /// Read and decode the whole lines from start_line to end_line (inclusive)
+    /// Also tells whether some bytes couldn't be decoded
+    fn read_run(
+        &self,
+        file: &mut File,
+        start_line: u64,
+        end_line: u64,
+    ) -> Result<(Vec<String>, Vec<u64>), CodedError> {
         // Calculate byte positions to read from
-        let start_pos = if start_line == 0 {
+        let start_pos = self.line_start_byte(start_line);
+
+        // Read up to and including the last byte of the newline at end_line
+        let end_pos = self.line_end_byte(end_line);
+
+        // Also read the newline ending the previous line, to check that the run starts right after it
+        let lead = if start_line > 0 {
+            self.newline_size()
+        } else {
             0
+        };
+        let buffer = Self::read_bytes(
+            file,
+            start_pos - lead,
+            Self::byte_span(start_pos, end_pos)? + lead,
+        )?;
+        let (previous_newline, buffer) = buffer.split_at(lead as usize);
+
+        let (decoded_text, had_errors) = self.decode(buffer);
+        self.check_run_edges(
+            (start_line, end_line),
+            (start_pos, end_pos),
+            previous_newline,
+            buffer,
+            had_errors.then_some(decoded_text.as_str()),
+        );
+        let invalid_lines = if had_errors {
+            self.invalid_lines(buffer, start_line, end_line)
+        } else {
+            Vec::new()
+        };
+        let decoded_text = decoded_text.trim_start_matches('\u{FEFF}');
+
+        // Split into lines
+        if self.record_separator != b'\n' {
+            let separator = self.record_separator as char;
+            let records = decoded_text.strip_suffix(separator).unwrap_or(decoded_text);
+            let lines = records.split(separator).map(|s| s.to_string()).collect();
+            return Ok((lines, invalid_lines));
+        }
+
+        let lines = decoded_text
+            .lines()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+
+        Ok((lines, invalid_lines))
+    }
+
+    /// Lines of a run that have invalid bytes, `run` holds the bytes from start_line to end_line
+    fn invalid_lines(&self, run: &[u8], start_line: u64, end_line: u64) -> Vec<u64> {
+        let run_start = self.line_start_byte(start_line);
+        (start_line..=end_line)
+            .filter(|&line| {
+                let from = (self.line_start_byte(line) - run_start) as usize;
+                let to = (self.line_end_byte(line) - run_start) as usize;
+                !self.is_valid(&run[from..to])
+            })
+            .collect()
+    }
+
+    /// Warn on stderr when a run of lines doesn't start right after a newline and end with one,
+    /// or when its first or last character couldn't be decoded: a multibyte character was likely
+    /// split, the index no longer matches the file (e.g. it was edited in place)
+    /// `decoded` is only given when decoding had errors
+    fn check_run_edges(
+        &self,
+        (start_line, end_line): (u64, u64),
+        (start_pos, end_pos): (u64, u64),
+        previous_newline: &[u8],
+        bytes: &[u8],
+        decoded: Option<&str>,
+    ) {
+        let on_newlines = (previous_newline.is_empty() || self.ends_with_newline(previous_newline))
+            && self.ends_with_newline(bytes);
+        let edges_replaced = decoded.is_some_and(|text| {
+            let text = text
+                .strip_suffix(self.record_separator as char)
+                .unwrap_or(text);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            text.starts_with('\u{FFFD}') || text.ends_with('\u{FFFD}')
+        });
+        if on_newlines && !edges_replaced {
+            return;
+        }
+
+        let problem = if on_newlines {
+            "start or end with a character that couldn't be decoded"
         } else {
-            self.index[(start_line - 1) as usize] + newline_size
+            "don't start and end on newlines"
+        };
+        let response = Response::Info {
+            message: format!(
+                "lines {}-{} (bytes {}-{}) {}, the index may no longer match the file",
+                start_line, end_line, start_pos, end_pos, problem
+            ),
         };
+        eprintln!("{}", serde_json::to_string(&response).unwrap());
+    }
+
+    /// Whether the bytes end with a newline (the record separator in ASCII-compatible encodings)
+    fn ends_with_newline(&self, bytes: &[u8]) -> bool {
+        match self.mode {
+            EncodingMode::AsciiCompatible => bytes.last() == Some(&self.record_separator),
+            EncodingMode::Utf16LE => bytes.ends_with(&[0x0A, 0x00]),
+            EncodingMode::Utf16BE => bytes.ends_with(&[0x00, 0x0A]),
+        }
+    }
+
+    /// Read only the first max_line_bytes of a line and mark it as clipped
+    fn read_clipped_line(&self, file: &mut File, line: u64) -> Result<(String, bool), CodedError> {
+        let mut bytes_to_read = self.max_line_bytes;
+
+        // Don't cut a UTF-16 code unit in half
+        if self.mode != EncodingMode::AsciiCompatible {
+            bytes_to_read -= bytes_to_read % 2;
+        }
+
+        let start_pos = self.line_start_byte(line);
+        let mut buffer = Self::read_bytes(file, start_pos, bytes_to_read)?;
+        // A character cut off by the clipping isn't a decoding error
+        buffer.truncate(self.whole_characters_len(&buffer));
+        let (decoded_text, had_errors) = self.decode(&buffer);
 
-        // Read up to and including the newline at actual_end_line
-        let end_pos = self.index[actual_end_line as usize] + newline_size;
-        let bytes_to_read = (end_pos - start_pos) as usize;
+        let mut clipped = decoded_text.trim_start_matches('\u{FEFF}').to_string();
+        clipped.push(CLIPPED_MARKER);
+        Ok((clipped, had_errors))
+    }
 
-        // Read the raw bytes
-        let mut file =
-            File::open(&self.file_path).map_err(|e| format!("Failed to open file: {}", e))?;
+    /// Number of bytes from start_pos to end_pos
+    /// An inconsistent index gives an error instead of an underflow (and a huge allocation)
+    fn byte_span(start_pos: u64, end_pos: u64) -> Result<u64, CodedError> {
+        end_pos.checked_sub(start_pos).ok_or_else(|| {
+            CodedError::new(
+                ErrorCode::Internal,
+                format!(
+                    "Invalid byte range: end ({}) is before start ({})",
+                    end_pos, start_pos
+                ),
+            )
+        })
+    }
+
+    /// Open the file for a read, a missing or unreadable file gets its own code
+    fn open_for_read(&self) -> Result<File, CodedError> {
+        File::open(&self.file_path).map_err(|e| {
+            let code = match e.kind() {
+                std::io::ErrorKind::NotFound => ErrorCode::FileNotFound,
+                std::io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+                _ => ErrorCode::Io,
+            };
+            CodedError::new(code, format!("Failed to open file: {}", e))
+        })
+    }
+
+    fn read_bytes(
+        file: &mut File,
+        start_pos: u64,
+        bytes_to_read: u64,
+    ) -> Result<Vec<u8>, CodedError> {
+        file.seek(SeekFrom::Start(start_pos)).map_err(|e| {
+            CodedError::new(
+                ErrorCode::Io,
+                format!("Failed to seek to position {}: {}", start_pos, e),
+            )
+        })?;
+
+        let mut buffer = vec![0u8; bytes_to_read as usize];
+        file.read_exact(&mut buffer).map_err(|e| {
+            CodedError::new(
+                ErrorCode::Io,
+                format!(
+                    "Failed to read {} bytes at byte {}: {}",
+                    bytes_to_read, start_pos, e
+                ),
+            )
+        })?;
+
+        Ok(buffer)
+    }
 
-        file.seek(SeekFrom::Start(start_pos))
-            .map_err(|e| format!("Failed to seek to position {}: {}", start_pos, e))?;
+    /// Remove ANSI escape sequences from the lines once they're parsed (see utils::parse_data)
+    /// The lines read keep them, so raw lines and exports stay as written
+    pub fn set_strip_ansi(&mut self, strip_ansi: bool) {
+        self.strip_ansi = strip_ansi;
+    }
 
-        let mut buffer = vec![0u8; bytes_to_read];
-        file.read_exact(&mut buffer)
-            .map_err(|e| format!("Failed to read {} bytes: {}", bytes_to_read, e))?;
+    pub fn strip_ansi(&self) -> bool {
+        self.strip_ansi
+    }
 
-        // Decode based on encoding mode
-        let decoded_text = match self.mode {
+    /// Decode raw bytes based on encoding mode
+    /// Invalid sequences are replaced with � (dropped with the Skip policy), the bool tells
+    /// whether there were any
+    fn decode(&self, buffer: &[u8]) -> (String, bool) {
+        let skip = self.decode_policy == DecodePolicy::Skip;
+        let encoding = match self.mode {
+            // Legacy ASCII-compatible encodings go through encoding_rs like UTF-16
+            EncodingMode::AsciiCompatible if self.ascii_compatible_encoding() != UTF_8 => {
+                self.ascii_compatible_encoding()
+            }
             EncodingMode::AsciiCompatible => {
-                // For ASCII-compatible encodings, we can use from_utf8_lossy
-                // or use encoding_rs for more accuracy if we stored the exact encoding
-                String::from_utf8_lossy(&buffer).into_owned()
+                return match std::str::from_utf8(buffer) {
+                    Ok(text) => (text.to_string(), false),
+                    Err(_) if skip => (
+                        buffer.utf8_chunks().map(|chunk| chunk.valid()).collect(),
+                        true,
+                    ),
+                    Err(_) => (String::from_utf8_lossy(buffer).into_owned(), true),
+                };
             }
-            EncodingMode::Utf16LE => {
-                // Decode UTF-16LE
-                let (decoded, _encoding, had_errors) = encoding_rs::UTF_16LE.decode(&buffer);
-                if had_errors {
-                    // Replace invalid sequences with �
-                    decoded.into_owned()
-                } else {
-                    decoded.into_owned()
-                }
+            EncodingMode::Utf16LE => encoding_rs::UTF_16LE,
+            EncodingMode::Utf16BE => encoding_rs::UTF_16BE,
+        };
+
+        if !skip {
+            let (decoded, _encoding, had_errors) = encoding.decode(buffer);
+            return (decoded.into_owned(), had_errors);
+        }
+
+        let mut decoder = encoding.new_decoder();
+        let mut text = String::new();
+        let mut rest = buffer;
+        let mut had_errors = false;
+        loop {
+            text.reserve(
+                decoder
+                    .max_utf8_buffer_length_without_replacement(rest.len())
+                    .unwrap_or(rest.len() * 3),
+            );
+            let (result, read) =
+                decoder.decode_to_string_without_replacement(rest, &mut text, true);
+            rest = &rest[read..];
+
+            match result {
+                DecoderResult::InputEmpty => return (text, had_errors),
+                DecoderResult::OutputFull => {}
+                // The malformed bytes are left out, decoding goes on after them
+                DecoderResult::Malformed(..) => had_errors = true,
+            }
+        }
+    }
+
+    /// Whether the bytes are all valid in the file's encoding
+    fn is_valid(&self, bytes: &[u8]) -> bool {
+        match self.mode {
+            EncodingMode::AsciiCompatible => self
+                .ascii_compatible_encoding()
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .is_some(),
+            EncodingMode::Utf16LE => encoding_rs::UTF_16LE
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .is_some(),
+            EncodingMode::Utf16BE => encoding_rs::UTF_16BE
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .is_some(),
+        }
+    }
+
+    /// With the Strict decode_policy, an error for the first of the lines that had invalid bytes
+    fn check_strict(&self, mut invalid_lines: impl Iterator<Item = u64>) -> Result<(), CodedError> {
+        if self.decode_policy != DecodePolicy::Strict {
+            return Ok(());
+        }
+        match invalid_lines.next() {
+            None => Ok(()),
+            Some(line) => Err(CodedError::new(
+                ErrorCode::DecodeError,
+                format!(
+                    "line {} (bytes {}-{}) has bytes that aren't valid {}, decode_policy is Strict",
+                    line,
+                    self.line_start_byte(line),
+                    self.line_end_byte(line),
+                    self.encoding_label
+                ),
+            )),
+        }
+    }
+
+    /// Length of `bytes` without a character cut off at their end
+    fn whole_characters_len(&self, bytes: &[u8]) -> usize {
+        let len = bytes.len();
+        match self.mode {
+            // Only UTF-8 is cut back, a character of a legacy multi-byte encoding
+            // (Shift_JIS, GBK...) cut off by the clipping decodes as �
+            EncodingMode::AsciiCompatible if self.ascii_compatible_encoding() != UTF_8 => len,
+            EncodingMode::AsciiCompatible => {
+                // The last character starts at most 3 continuation bytes back
+                let Some(start) = (len.saturating_sub(4)..len)
+                    .rev()
+                    .find(|&i| bytes[i] & 0xC0 != 0x80)
+                else {
+                    return len;
+                };
+                let needed = match bytes[start] {
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => 1,
+                };
+                if len - start < needed { start } else { len }
             }
-            EncodingMode::Utf16BE => {
-                // Decode UTF-16BE
-                let (decoded, _encoding, had_errors) = encoding_rs::UTF_16BE.decode(&buffer);
-                if had_errors {
-                    // Replace invalid sequences with �
-                    decoded.into_owned()
+            // A high surrogate needs the low surrogate that follows it
+            EncodingMode::Utf16LE | EncodingMode::Utf16BE if len >= 2 => {
+                let last = [bytes[len - 2], bytes[len - 1]];
+                let unit = match self.mode {
+                    EncodingMode::Utf16LE => u16::from_le_bytes(last),
+                    _ => u16::from_be_bytes(last),
+                };
+                if (0xD800..0xDC00).contains(&unit) {
+                    len - 2
                 } else {
-                    decoded.into_owned()
+                    len
                 }
             }
-        };
-        let decoded_text = decoded_text.trim_start_matches('\u{FEFF}');
-        // Split into lines
-        let lines = decoded_text
-            .lines()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
+            _ => len,
+        }
+    }
+}
 
-        Ok(lines)
+impl Drop for FileProcessor {
+    fn drop(&mut self) {
+        // Remove the temporary decompressed copy
+        if self.compression.is_some() {
+            let _ = fs::remove_file(&self.file_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::test_files::TempFile;
+
+    #[test]
+    fn read_leading_blank_line() {
+        let file = TempFile::new(b"\nfoo\nbar\n");
+        let processor = file.open("utf-8");
+
+        assert_eq!(processor.index.len(), 3);
+        assert_eq!(
+            processor.read_lines_range(0, 2).unwrap(),
+            ["", "foo", "bar"]
+        );
+        assert_eq!(processor.line_start_byte(1), 1);
+    }
+
+    #[test]
+    fn set_legacy_encoding() {
+        let file = TempFile::new(b"caf\xe9\n");
+        let mut processor = file.open("utf-8");
+        assert_eq!(processor.read_lines_range(0, 0).unwrap(), ["caf\u{FFFD}"]);
+
+        processor.set_encoding("latin1").unwrap();
+        assert_eq!(processor.read_lines_range(0, 0).unwrap(), ["café"]);
+    }
+
+    #[test]
+    fn read_utf16be_lines() {
+        let text = "first\nsecond\n";
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let file = TempFile::new(&bytes);
+        let processor = file.open("utf-16be");
+
+        assert_eq!(processor.index.len(), 2);
+        assert_eq!(
+            processor.read_lines_range(0, 1).unwrap(),
+            ["first", "second"]
+        );
+        // Lines start right after a newline's 0x0A byte and end on the last byte of the file
+        assert_eq!(processor.line_start_byte(1), 12);
+        assert_eq!(processor.line_end_byte(1), bytes.len() as u64);
     }
 }
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, sync::Arc};
+
+/// Lines per cached block
+pub const BLOCK_LINES: u64 = 1000;
+
+/// Memory the cache may use for decoded lines (32MB)
+pub const DEFAULT_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+/// Decoded lines of one block, with the line numbers that were clipped
+/// and the ones that had bytes that couldn't be decoded
+pub struct CachedBlock {
+    pub lines: Vec<String>,
+    pub clipped_lines: Vec<u64>,
+    pub decode_error_lines: Vec<u64>,
+}
+
+impl CachedBlock {
+    /// Approximate memory used by the block
+    fn size(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| line.len() + std::mem::size_of::<String>())
+            .sum::<usize>()
+            + (self.clipped_lines.len() + self.decode_error_lines.len())
+                * std::mem::size_of::<u64>()
+    }
+}
+
+/// Recently read blocks of decoded lines, the least recently used are evicted
+/// once the cache holds more than max_bytes
+pub struct LineCache {
+    blocks: HashMap<u64, (Arc<CachedBlock>, u64)>, // Block and the tick it was last used at
+    max_bytes: usize,
+    bytes: usize,
+    tick: u64,
+}
+
+impl LineCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            blocks: HashMap::new(),
+            max_bytes,
+            bytes: 0,
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, block: u64) -> Option<Arc<CachedBlock>> {
+        self.tick += 1;
+        let (cached, last_used) = self.blocks.get_mut(&block)?;
+        *last_used = self.tick;
+        Some(Arc::clone(cached))
+    }
+
+    pub fn insert(&mut self, block: u64, cached: Arc<CachedBlock>) {
+        let size = cached.size();
+        if size > self.max_bytes {
+            return;
+        }
+
+        if let Some((old, _)) = self.blocks.remove(&block) {
+            self.bytes -= old.size();
+        }
+
+        while self.bytes + size > self.max_bytes {
+            let Some(oldest) = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&block, _)| block)
+            else {
+                break;
+            };
+            if let Some((evicted, _)) = self.blocks.remove(&oldest) {
+                self.bytes -= evicted.size();
+            }
+        }
+
+        self.tick += 1;
+        self.bytes += size;
+        self.blocks.insert(block, (cached, self.tick));
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.bytes = 0;
+    }
+}
@@ -0,0 +1,238 @@
+/// Lines whose offsets are stored relative to the same base
+const INDEX_BLOCK_LINES: usize = 1024;
+
+/// Stored instead of an offset too far from its block's base, the offset is then in `far`
+const FAR_OFFSET: u32 = u32::MAX;
+
+/// Byte offsets of the newlines ending each line, line n ends at the newline at `get(n)`
+/// (the 0x0A byte, which is the second byte of a UTF-16BE newline)
+/// Offsets take 4 bytes per line instead of 8: each block of lines keeps the offset of its
+/// first newline, and the others are stored as distances from it. Lookups stay O(1)
+#[derive(Default)]
+pub struct LineIndex {
+    bases: Vec<u64>,
+    offsets: Vec<u32>,
+    far: Vec<(usize, u64)>, // Lines more than 4GB past their block's base, and their offset
+}
+
+impl LineIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn push(&mut self, newline: u64) {
+        let line = self.offsets.len();
+        if line.is_multiple_of(INDEX_BLOCK_LINES) {
+            self.bases.push(newline);
+        }
+
+        let distance = newline - self.bases[line / INDEX_BLOCK_LINES];
+        match u32::try_from(distance) {
+            Ok(distance) if distance != FAR_OFFSET => self.offsets.push(distance),
+            _ => {
+                self.offsets.push(FAR_OFFSET);
+                self.far.push((line, newline));
+            }
+        }
+    }
+
+    /// Offset of the newline ending a line, panics past the last line like slice indexing
+    pub fn get(&self, line: usize) -> u64 {
+        match self.offsets[line] {
+            FAR_OFFSET => {
+                let pos = self.far.partition_point(|&(far_line, _)| far_line < line);
+                self.far[pos].1
+            }
+            distance => self.bases[line / INDEX_BLOCK_LINES] + distance as u64,
+        }
+    }
+
+    pub fn last(&self) -> Option<u64> {
+        self.len().checked_sub(1).map(|line| self.get(line))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len()).map(|line| self.get(line))
+    }
+
+    /// Index of the first line whose newline doesn't satisfy `pred` (the offsets are sorted)
+    pub fn partition_point(&self, pred: impl Fn(u64) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(self.get(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// Keep the first `lines` lines
+    pub fn truncate(&mut self, lines: usize) {
+        self.offsets.truncate(lines);
+        self.bases.truncate(lines.div_ceil(INDEX_BLOCK_LINES));
+        let far_kept = self.far.partition_point(|&(line, _)| line < lines);
+        self.far.truncate(far_kept);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{hint::black_box, time::Instant};
+
+    const GB: u64 = 1 << 30;
+
+    fn index_of(newlines: &[u64]) -> LineIndex {
+        let mut index = LineIndex::new();
+        for &newline in newlines {
+            index.push(newline);
+        }
+        index
+    }
+
+    #[test]
+    fn far_offsets() {
+        // Exactly u32::MAX bytes past the block's base is stored apart too
+        let index = index_of(&[10, u32::MAX as u64 + 10]);
+        assert_eq!(index.far, [(1, u32::MAX as u64 + 10)]);
+        assert_eq!(index.get(1), u32::MAX as u64 + 10);
+
+        // A 5GB line, then lines past it in the same block
+        let newlines = [10, 20, 5 * GB, 5 * GB + 1, 6 * GB];
+        let index = index_of(&newlines);
+        assert_eq!(index.far.len(), 3);
+        assert_eq!(index.iter().collect::<Vec<u64>>(), newlines);
+        assert_eq!(index.last(), Some(6 * GB));
+    }
+
+    #[test]
+    fn truncate_then_push() {
+        let newlines: Vec<u64> = (0..3000).map(|line| line * 100).collect();
+        let mut index = index_of(&newlines);
+
+        // In the middle of the second block: its base stays, the new lines use it
+        index.truncate(1500);
+        assert_eq!(index.len(), 1500);
+        assert_eq!(index.bases.len(), 2);
+        index.push(149_950);
+        index.push(6 * GB);
+        assert_eq!(index.get(1499), 149_900);
+        assert_eq!(index.get(1500), 149_950);
+        assert_eq!(index.get(1501), 6 * GB);
+
+        // The far line goes with the lines it belongs to
+        index.truncate(1501);
+        assert!(index.far.is_empty());
+        index.push(150_000);
+        assert_eq!(index.last(), Some(150_000));
+
+        // On a block boundary, the next line starts a new block
+        index.truncate(1024);
+        assert_eq!(index.bases.len(), 1);
+        index.push(7 * GB);
+        assert_eq!(index.bases, [0, 7 * GB]);
+        assert_eq!(index.get(1024), 7 * GB);
+
+        index.clear();
+        assert_eq!(index.len(), 0);
+        assert_eq!(index.last(), None);
+    }
+
+    #[test]
+    fn partition_point_finds_lines() {
+        let newlines: Vec<u64> = (0..2500).map(|line| line * 10 + 9).collect();
+        let index = index_of(&newlines);
+
+        for byte in [0, 9, 10, 12_345, 24_999, 25_000] {
+            assert_eq!(
+                index.partition_point(|newline| newline < byte),
+                newlines.partition_point(|&newline| newline < byte),
+                "byte {}",
+                byte
+            );
+        }
+        assert_eq!(LineIndex::new().partition_point(|_| true), 0);
+    }
+
+    /// Memory and lookup speed against the Vec<u64> the index replaced, on 10M lines
+    /// cargo test --release line_index_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn line_index_benchmark() {
+        const LINES: u64 = 10_000_000;
+        const LOOKUPS: usize = 10_000_000;
+
+        let newlines: Vec<u64> = (0..LINES).map(|line| line * 93 + line % 7).collect();
+        let index = index_of(&newlines);
+
+        // Bytes stored, both grow the same way when built line by line
+        let vec_bytes = newlines.len() * size_of::<u64>();
+        let index_bytes = index.bases.len() * size_of::<u64>()
+            + index.offsets.len() * size_of::<u32>()
+            + index.far.len() * size_of::<(usize, u64)>();
+        println!(
+            "memory: Vec<u64> {:.1}MB, LineIndex {:.1}MB",
+            vec_bytes as f64 / 1e6,
+            index_bytes as f64 / 1e6
+        );
+
+        // Spread over the file without a random number crate (Knuth's multiplicative hash)
+        let lines: Vec<usize> = (0..LOOKUPS)
+            .map(|i| (i as u64).wrapping_mul(2_654_435_761) % LINES)
+            .map(|line| line as usize)
+            .collect();
+
+        let started_at = Instant::now();
+        let sum: u64 = lines.iter().map(|&line| black_box(&newlines)[line]).sum();
+        let vec_time = started_at.elapsed();
+
+        let started_at = Instant::now();
+        let index_sum: u64 = lines.iter().map(|&line| black_box(&index).get(line)).sum();
+        let index_time = started_at.elapsed();
+
+        assert_eq!(sum, index_sum);
+        println!(
+            "{} random lookups: Vec<u64> {:?}, LineIndex {:?}",
+            LOOKUPS, vec_time, index_time
+        );
+
+        let bytes: Vec<u64> = lines
+            .iter()
+            .take(LOOKUPS / 10)
+            .map(|&line| newlines[line])
+            .collect();
+
+        let started_at = Instant::now();
+        let found: usize = bytes
+            .iter()
+            .map(|&byte| newlines.partition_point(|&newline| newline < byte))
+            .sum();
+        let vec_time = started_at.elapsed();
+
+        let started_at = Instant::now();
+        let index_found: usize = bytes
+            .iter()
+            .map(|&byte| index.partition_point(|newline| newline < byte))
+            .sum();
+        let index_time = started_at.elapsed();
+
+        assert_eq!(found, index_found);
+        println!(
+            "{} partition_point: Vec<u64> {:?}, LineIndex {:?}",
+            bytes.len(),
+            vec_time,
+            index_time
+        );
+    }
+}
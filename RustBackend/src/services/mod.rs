@@ -1,10 +1,137 @@
 pub mod commands;
 pub mod commands_processor;
+mod compression;
+mod file_access;
 mod file_processor;
+mod line_cache;
+mod line_index;
+pub mod output;
+#[cfg(test)]
+mod test_files;
+pub use compression::{
+    Compression, decompress_to_temp, detect_compression, estimate_uncompressed_size,
+};
+pub use file_access::{create_checked_output, metadata_checked, open_checked, write_error};
 pub use file_processor::FileProcessor;
 
+use crate::types::{LogFormat, NotifyMode, Response};
+
+use std::{
+    any::Any,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+};
+
 pub struct FileState {
     pub processor: FileProcessor,
-    pub regex_pattern: Option<regex::Regex>,
+    pub log_format: Option<LogFormat>,
+    pub line_parser: Option<commands::LineParser>,
     pub nbr_columns: Option<u8>,
+    /// Lines kept by the last filter, cleared when the index is rebuilt
+    pub filter: Option<commands::FilterMatches>,
+}
+
+/// Watcher options given to OpenFile, kept so SetFollow can restart the watcher
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WatchSettings {
+    pub heartbeat: bool,
+    pub debounce: bool,
+    pub max_lines_per_event: usize,
+    pub notify: NotifyMode,
+}
+
+/// One opened file and its watcher thread, addressed by a file_id
+pub struct Session {
+    pub file_state: Arc<Mutex<Option<FileState>>>,
+    pub watcher_handle: Option<JoinHandle<()>>,
+    pub should_stop: Arc<AtomicBool>,
+    pub watch_settings: WatchSettings,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            file_state: Arc::new(Mutex::new(None)),
+            watcher_handle: None,
+            should_stop: Arc::new(AtomicBool::new(false)),
+            watch_settings: WatchSettings::default(),
+        }
+    }
+
+    /// Stop the watcher thread if there is one, waiting for it to finish
+    /// A watcher that panicked is reported on stderr, the caller goes on (e.g. opening the next file)
+    pub fn stop_watcher(&mut self) {
+        if let Some(handle) = self.watcher_handle.take() {
+            self.should_stop.store(true, Ordering::Relaxed); //Hey thread, stop what you're doing.
+            let joined = handle.join(); //i'm waiting for you...
+            if let Err(payload) = joined {
+                let response = Response::Info {
+                    message: format!(
+                        "The file watcher had stopped after a panic: {}",
+                        panic_message(payload.as_ref())
+                    ),
+                };
+                eprintln!("{}", serde_json::to_string(&response).unwrap());
+            }
+            self.should_stop.store(false, Ordering::Relaxed); //reset the stop signal for another use.
+        }
+    }
+}
+
+/// Text given to panic!, which is a &str or a String
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.stop_watcher();
+    }
+}
+
+/// Lets a command read while OpenFile is still indexing abort that indexing
+/// Commands run one at a time, so at most one session is indexing
+#[derive(Default)]
+pub struct OpenCancel {
+    /// Session key (file_id) of the file being indexed
+    indexing: Mutex<Option<String>>,
+    cancelled: AtomicBool,
+}
+
+impl OpenCancel {
+    pub fn start(&self, key: &str) {
+        let mut indexing = self.indexing.lock().unwrap();
+        self.cancelled.store(false, Ordering::Relaxed);
+        *indexing = Some(key.to_string());
+    }
+
+    /// Ends the indexing begun by start, and tells whether it was asked to stop
+    /// The request is cleared, it can't leak into a later open
+    pub fn finish(&self) -> bool {
+        let mut indexing = self.indexing.lock().unwrap();
+        *indexing = None;
+        self.cancelled.swap(false, Ordering::Relaxed)
+    }
+
+    /// Ask the OpenFile running in session `key` to stop
+    /// Returns false if that session isn't indexing, other sessions are left alone
+    pub fn request(&self, key: &str) -> bool {
+        let indexing = self.indexing.lock().unwrap();
+        if indexing.as_deref() != Some(key) {
+            return false;
+        }
+        self.cancelled.store(true, Ordering::Relaxed);
+        true
+    }
+
+    pub fn flag(&self) -> &AtomicBool {
+        &self.cancelled
+    }
 }
@@ -0,0 +1,48 @@
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+use crate::types::{ProtocolFormat, Response, SessionResponse};
+
+/// Whether responses are sent as MessagePack instead of JSON lines, set by SetProtocol
+static MSGPACK: AtomicBool = AtomicBool::new(false);
+
+/// Writes a response to stdout in the current format and flushes it
+/// JSON is one response per line, MessagePack is prefixed with its length (4 bytes, big-endian)
+pub fn send(response: &impl Serialize) {
+    let mut stdout = io::stdout().lock();
+    write_response(&mut stdout, response);
+}
+
+/// Writes a response sent while a command of session `file_id` runs, tagged so the client
+/// can route it to the right pane
+pub fn send_to(file_id: &Option<String>, response: Response) {
+    send(&SessionResponse {
+        file_id: file_id.clone(),
+        response,
+    });
+}
+
+/// Answers SetProtocol in the current format then switches to the new one
+/// stdout stays locked in between, so the answer is the last response in the old format
+/// even with watchers sending their own
+pub fn switch_format(format: ProtocolFormat, response: &impl Serialize) {
+    let mut stdout = io::stdout().lock();
+    write_response(&mut stdout, response);
+    MSGPACK.store(format == ProtocolFormat::MessagePack, Ordering::Relaxed);
+}
+
+fn write_response(stdout: &mut impl Write, response: &impl Serialize) {
+    // A closed stdout means the client is gone, there's no one left to tell
+    if MSGPACK.load(Ordering::Relaxed) {
+        let encoded = rmp_serde::to_vec_named(response).unwrap();
+        let _ = stdout.write_all(&(encoded.len() as u32).to_be_bytes());
+        let _ = stdout.write_all(&encoded);
+    } else {
+        let _ = writeln!(stdout, "{}", serde_json::to_string(response).unwrap());
+    }
+    let _ = stdout.flush();
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use crate::services::{FileProcessor, FileState, file_processor::EncodingSource};
+
+/// A file in the temp directory, deleted when dropped
+pub struct TempFile {
+    pub path: String,
+}
+
+impl TempFile {
+    pub fn new(contents: &[u8]) -> Self {
+        // Tests run in parallel, each file gets its own name
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "fatfile-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, contents).unwrap();
+
+        Self {
+            path: path.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Indexes the file, read in `encoding` instead of a detected one
+    pub fn open(&self, encoding: &str) -> FileProcessor {
+        FileProcessor::new(
+            &self.path,
+            EncodingSource::Assume(encoding.to_string()),
+            None,
+            b'\n',
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+    }
+
+    /// The UTF-8 file opened without a format, held the way a session holds it
+    pub fn file_state(&self) -> Arc<Mutex<Option<FileState>>> {
+        Arc::new(Mutex::new(Some(FileState {
+            processor: self.open("utf-8"),
+            log_format: None,
+            line_parser: None,
+            nbr_columns: None,
+            filter: None,
+        })))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
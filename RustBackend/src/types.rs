@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[allow(clippy::enum_variant_names)]
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum LogFormat {
     CommonLogFormat,
     SyslogRFC3164,
@@ -9,18 +11,130 @@ pub enum LogFormat {
     W3CExtended,
     CommonEventFormat,
     NCSACombined,
+    DockerJson,
+    CriLog,
     Other,
 }
 
+/// Optional settings for OpenFile, given next to `path`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OpenFileOptions {
+    // Emit NoChange on every watcher tick where the file didn't change
+    #[serde(default)]
+    pub heartbeat: bool,
+
+    // Lines longer than this are clipped when read, defaults to 1MB
+    #[serde(default)]
+    pub max_line_bytes: Option<u64>,
+
+    // Split LinesAdded events larger than this, defaults to 1000 lines
+    #[serde(default)]
+    pub max_lines_per_event: Option<usize>,
+
+    // What to do with bytes that aren't valid in the file's encoding, defaults to Replace
+    #[serde(default)]
+    pub decode_policy: DecodePolicy,
+
+    // Hold new lines while the file keeps growing and send them together
+    #[serde(default)]
+    pub debounce: bool,
+
+    // Watcher events to send, defaults to All
+    #[serde(default)]
+    pub notify: NotifyMode,
+
+    // Keep the file open for the watcher instead of reopening it on every change
+    #[serde(default)]
+    pub keep_handle_open: bool,
+
+    // Bytes sampled to detect the encoding, defaults to 64KB
+    #[serde(default)]
+    pub detect_bytes: Option<usize>,
+
+    // Use this encoding instead of detecting it, detect_bytes is then ignored
+    #[serde(default)]
+    pub assume_encoding: Option<String>,
+
+    // Watch the file for changes, defaults to true
+    #[serde(default)]
+    pub follow: Option<bool>,
+
+    // Byte that ends each line instead of \n (e.g. 0 for NUL-separated records),
+    // only for ASCII-compatible encodings
+    #[serde(default)]
+    pub record_separator: Option<u8>,
+
+    // Refuse files larger than this many bytes, defaults to 16GB
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+
+    // Open the file even if it's larger than max_file_size
+    #[serde(default)]
+    pub allow_large: bool,
+
+    // Also send the lines around this line once the file is opened
+    #[serde(default)]
+    pub goto_line: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Command {
     GetFileEncoding {
         path: String,
+
+        // Bytes sampled for detection, defaults to 64KB
+        #[serde(default)]
+        detect_bytes: Option<usize>,
+    },
+    DetectFormat {
+        path: String,
+    },
+    GetFileInfo {
+        path: String,
     },
     OpenFile {
         path: String,
+
+        #[serde(flatten)]
+        options: OpenFileOptions,
+    },
+    // Like OpenFile, but only the lines written from now on are indexed
+    OpenTail {
+        path: String,
+
+        #[serde(flatten)]
+        options: OpenFileOptions,
+    },
+    // Like OpenFile, but only the lines starting in [start_byte, end_byte) are indexed
+    OpenWindow {
+        path: String,
+        start_byte: u64,
+        end_byte: u64,
+
+        #[serde(flatten)]
+        options: OpenFileOptions,
+    },
+    CloseFile,
+    CancelOpen,
+    // Format of the responses sent after this one, JSON until changed
+    SetProtocol {
+        format: ProtocolFormat,
     },
     GetParsingInformation,
+    ProbeFormats {
+        // Lines sampled from the top of the file, defaults to 100, at most 10 000
+        #[serde(default)]
+        sample_lines: Option<u64>,
+    },
+    GetColumnNames,
+    Reindex,
+    VerifyIndex,
+    SetFollow {
+        enabled: bool,
+    },
+    SetEncoding {
+        label: String,
+    },
     ParseFile {
         log_format: LogFormat,
 
@@ -30,17 +144,245 @@ pub enum Command {
         //defaults to None - if not provided, no validation is performed
         #[serde(default)]
         nbr_columns: Option<u8>,
+
+        // Number of lines from the start of the file to test the pattern against
+        #[serde(default)]
+        sample_lines: Option<u64>,
+
+        // Remove ANSI escape sequences (colors...) from the lines before they're parsed
+        #[serde(default)]
+        strip_ansi: bool,
+
+        // Remove whitespace around each captured group
+        #[serde(default)]
+        trim: bool,
+    },
+    ParseDelimited {
+        delimiter: char,
+
+        // Lines with another number of columns are returned unparsed
+        #[serde(default)]
+        nbr_columns: Option<u8>,
     },
     GetChunk {
         start_line: u64,
         end_line: u64,
+
+        // Keep optional columns that didn't match as null, answers with a NullableChunk
+        #[serde(default)]
+        explicit_nulls: bool,
+
+        // Return the lines newest first, from end_line down to start_line
+        #[serde(default)]
+        reverse: bool,
+
+        // Also send the unparsed lines, in `raw`
+        #[serde(default)]
+        include_raw: bool,
+
+        // Cut column values longer than this, the whole value is read with GetFullColumn
+        #[serde(default)]
+        max_column_bytes: Option<usize>,
+    },
+    // Like GetChunk, with the number of lines to read instead of the last line
+    GetChunkCount {
+        start_line: u64,
+        count: u64,
+
+        #[serde(default)]
+        explicit_nulls: bool,
+        #[serde(default)]
+        reverse: bool,
+        #[serde(default)]
+        include_raw: bool,
+        #[serde(default)]
+        max_column_bytes: Option<usize>,
+    },
+    GetFullColumn {
+        line: u64,
+        column: u8,
+
+        // Count columns like a chunk read with explicit_nulls
+        #[serde(default)]
+        explicit_nulls: bool,
+    },
+    GetChunkByBytes {
+        start_byte: u64,
+        end_byte: u64,
+    },
+    GetChunkByPercent {
+        percent: f32,
+        window: u64,
+    },
+    GetContext {
+        line: u64,
+        before: u64,
+        after: u64,
+    },
+    GetLineLengths {
+        start_line: u64,
+        end_line: u64,
+    },
+    GetLines {
+        lines: Vec<u64>,
+    },
+    GetRawBytes {
+        start_line: u64,
+        end_line: u64,
+    },
+    GetRawChunk {
+        start_line: u64,
+        end_line: u64,
     },
     Search {
         pattern: String,
+
+        // Search the raw line instead of the parsed columns
+        #[serde(default)]
+        raw: bool,
+
+        // Make ^ and $ anchor to the line rather than to each column (implies raw)
+        #[serde(default)]
+        anchor_to_line: bool,
+
+        #[serde(default)]
+        case_insensitive: bool,
+
+        // Only match on word boundaries
+        #[serde(default)]
+        whole_word: bool,
+
+        // Let . match \n, only useful for records containing newlines
+        #[serde(default)]
+        dot_matches_newline: bool,
+
+        // Verbose mode: whitespace in the pattern is ignored and # starts a comment
+        #[serde(default)]
+        ignore_whitespace: bool,
+
+        // Include the column name in each match
+        #[serde(default)]
+        column_names: bool,
+
+        // Send matches in SearchResultsPartial messages while searching
+        #[serde(default)]
+        stream: bool,
+
+        // Count start_index and end_index in characters (Unicode code points) instead of bytes
+        #[serde(default)]
+        char_offsets: bool,
+
+        // Search these columns joined with spaces, as a single text
+        #[serde(default)]
+        join_columns: Option<Vec<u8>>,
+
+        // Search every parsed column except these
+        #[serde(default)]
+        exclude_columns: Option<Vec<u8>>,
+
+        // Compiled regex size limit in bytes, defaults to the regex crate's limit
+        #[serde(default)]
+        size_limit: Option<usize>,
+
+        // Lazy DFA cache size limit in bytes, defaults to the regex crate's limit
+        #[serde(default)]
+        dfa_size_limit: Option<usize>,
+
+        // Lines searched per parallel task, defaults to 10 000
+        #[serde(default)]
+        chunk_size: Option<usize>,
+    },
+    ExportMatches {
+        // Absolute path of the file to write, replaced if it exists
+        out_path: String,
+        line_numbers: Vec<u64>,
+
+        // Write decoded UTF-8 instead of the original bytes
+        #[serde(default)]
+        utf8: bool,
+    },
+    ReplaceToFile {
+        // Regex whose matches are replaced, in every line
+        pattern: String,
+        // Text put in place of each match, $1 or ${name} insert a capture group
+        replacement: String,
+        // Absolute path of the file to write, replaced if it exists
+        out_path: String,
+    },
+    CountByColumn {
+        column: u8,
+    },
+    ParseStats,
+    TimeHistogram {
+        buckets: u32,
+    },
+    MatchTimeline {
+        pattern: String,
+        buckets: u32,
+    },
+    NthMatch {
+        pattern: String,
+        // Number of the match to go to, 1 for the first matching line
+        n: u64,
+    },
+    SearchBytes {
+        // Bytes to find, as hex digits, e.g. "DE AD BE EF"
+        hex: String,
     },
     Filter {
         pattern: String,
+
+        // Lines scanned per parallel task, defaults to 10 000
+        #[serde(default)]
+        chunk_size: Option<usize>,
+
+        // Keep only the first of identical lines, with their number of occurrences
+        #[serde(default)]
+        distinct: bool,
+
+        // Compare lines by this parsed column instead of the whole line (implies distinct)
+        #[serde(default)]
+        distinct_column: Option<u8>,
+    },
+    GetFilterPage {
+        offset: u64,
+        count: u64,
     },
+    // Capture groups of the first match on each line, whatever the file's format
+    Extract {
+        pattern: String,
+
+        // Lines scanned per parallel task, defaults to 10 000
+        #[serde(default)]
+        chunk_size: Option<usize>,
+    },
+    FilterTimeRange {
+        start: String,
+        end: String,
+
+        // Keep lines without a parseable timestamp instead of dropping them
+        #[serde(default)]
+        keep_unparsed: bool,
+    },
+}
+
+/// A command addressed to a session, e.g. {"file_id": "a", "GetChunk": {...}}
+/// Commands without a file_id go to the default session
+#[derive(Debug, Deserialize)]
+pub struct SessionCommand {
+    #[serde(default)]
+    pub file_id: Option<String>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// A response tagged with the session it comes from (omitted for the default session)
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(flatten)]
+    pub response: Response,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,50 +391,432 @@ pub enum Response {
         encoding: String,
         is_supported: bool,
     },
+    FileInfo {
+        size_bytes: u64,
+        // Last modification, in milliseconds since the Unix epoch (null if unknown)
+        modified: Option<i64>,
+        // false for directories and other special files
+        is_file: bool,
+    },
     FileOpened {
         line_count: u64,
+        #[serde(default)]
+        trailing_newline: bool,
+        // Bytes before line 0 that weren't indexed, set when OpenTail or OpenWindow skipped any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        skipped_bytes: Option<u64>,
+        // Byte where an OpenWindow's last line ends, nothing after it is indexed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window_end: Option<u64>,
+        // Problems that didn't prevent the open, e.g. an unsupported encoding read as utf-8
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+        // Timestamps of the first and last lines in ms since the epoch, with the offset written
+        // in the line applied (UTC without one), null when the line has no timestamp with a year
+        #[serde(default)]
+        first_timestamp: Option<i64>,
+        #[serde(default)]
+        last_timestamp: Option<i64>,
+    },
+    FileClosed,
+    ProtocolSet {
+        format: ProtocolFormat,
+    },
+    CompressedFile {
+        compression: String,
+        estimated_size: Option<u64>,
+    },
+    Reindexed {
+        line_count: u64,
+        trailing_newline: bool,
+    },
+    IndexVerified {
+        matches: bool,
+        indexed_lines: u64,
+        scanned_lines: u64,
+        // First line whose newline differs, with where the index and the scan put it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        divergence_line: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indexed_newline: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scanned_newline: Option<u64>,
+    },
+    FollowChanged {
+        enabled: bool,
+    },
+    EncodingChanged {
+        encoding: String,
+        line_count: u64,
+    },
+    ColumnNames {
+        names: Vec<String>,
     },
     ParsingInformation {
         log_format: LogFormat,
+
+        // Only set when ParseFile was asked to sample lines
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sampled: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        matched: Option<u64>,
+    },
+    FormatProbe {
+        // Every built-in format, best score first
+        formats: Vec<FormatScore>,
+        // Non-blank lines the scores are computed on
+        sampled: u64,
     },
     Chunk {
         data: Vec<Vec<String>>,
         start_line: u64,
         end_line: u64,
+
+        // Lines clipped to max_line_bytes
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        clipped_lines: Vec<u64>,
+
+        // Bytes of the file covered by the lines, newlines included (end excluded)
+        start_byte: u64,
+        end_byte: u64,
+
+        // Some bytes of the lines couldn't be decoded and were replaced with �,
+        // the file may not be in the encoding it's read with (see SetEncoding)
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        had_decode_errors: bool,
+
+        // Line the chunk was requested around, only set by GetContext
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        focal_line: Option<u64>,
+
+        // Unparsed text of each line of data, in the same order, only with include_raw
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<Vec<String>>,
+
+        // Line and column of the values cut to max_column_bytes
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        truncated_columns: Vec<(u64, u8)>,
+    },
+    NullableChunk {
+        data: Vec<Vec<Option<String>>>,
+        start_line: u64,
+        end_line: u64,
+
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        clipped_lines: Vec<u64>,
+
+        start_byte: u64,
+        end_byte: u64,
+
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        had_decode_errors: bool,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<Vec<String>>,
+
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        truncated_columns: Vec<(u64, u8)>,
+    },
+    FullColumn {
+        line: u64,
+        column: u8,
+        // None for an optional column that didn't match (with explicit_nulls)
+        value: Option<String>,
+    },
+    Lines {
+        lines: BTreeMap<u64, Vec<String>>,
+        invalid_lines: Vec<u64>,
+    },
+    RawBytes {
+        // Base64 of the bytes as they are in the file
+        data: String,
+        start_line: u64,
+        end_line: u64,
+        start_byte: u64,
+        byte_count: u64,
+
+        // The range was larger than the cap and was cut
+        truncated: bool,
+    },
+    LineLengths {
+        lengths: Vec<u64>,
+        start_line: u64,
+        end_line: u64,
     },
     SearchResults {
         matches: Vec<SearchMatch>,
         total_matches: u32,
         search_complete: bool,
+        #[serde(default)]
+        duration_ms: u64,
+        #[serde(default)]
+        lines_scanned: u64,
+        #[serde(default)]
+        per_column_counts: Vec<u32>,
+        // What `column` and the offsets of the matches refer to
+        #[serde(default)]
+        mode: SearchMode,
+    },
+    Exported {
+        lines_written: u64,
+        bytes_written: u64,
+        invalid_lines: Vec<u64>,
+    },
+    Replaced {
+        lines_written: u64,
+        lines_changed: u64,
+        replacements: u64,
+    },
+    ByteSearchResults {
+        matches: Vec<ByteMatch>,
+        total_matches: u32,
+        search_complete: bool,
+        #[serde(default)]
+        duration_ms: u64,
+    },
+    Extracted {
+        // Names of the capture groups, column_N for unnamed ones
+        columns: Vec<String>,
+        // Matching lines in file order, at most 1000
+        rows: Vec<ExtractedRow>,
+        // false when the scan stopped at 1000 rows
+        complete: bool,
+        lines_scanned: u64,
+        duration_ms: u64,
+    },
+    ColumnCounts {
+        column: u8,
+        // Value and number of lines, most frequent first, at most 1000 values
+        counts: Vec<(String, u64)>,
+        distinct_values: u64,
+        // Lines that didn't parse, left out of the counts
+        unparsed_lines: u64,
+        // false when part of the file couldn't be read, its lines aren't counted
+        complete: bool,
+        duration_ms: u64,
+    },
+    ParseStats {
+        total: u64,
+        // Lines split into the expected number of columns
+        matched: u64,
+        // Lines that matched but not into nbr_columns columns
+        column_mismatch: u64,
+        unmatched: u64,
+        // false when part of the file couldn't be read, its lines aren't counted
+        complete: bool,
+    },
+    TimeHistogram {
+        // Lines per bucket, oldest first
+        counts: Vec<u64>,
+        // Bucket i goes from boundaries[i] (included) to boundaries[i + 1] (excluded)
+        boundaries: Vec<String>,
+        // Lines without a parseable timestamp
+        unknown: u64,
+        // false when part of the file couldn't be read, its lines aren't counted
+        complete: bool,
+        duration_ms: u64,
+    },
+    MatchTimeline {
+        // Matching lines per bucket, oldest first
+        counts: Vec<u64>,
+        // Bucket i goes from boundaries[i] (included) to boundaries[i + 1] (excluded)
+        boundaries: Vec<String>,
+        // Matching lines without a parseable timestamp
+        untimed: u64,
+        // false when part of the file couldn't be read, its lines aren't counted
+        complete: bool,
+        duration_ms: u64,
+    },
+    SearchResultsPartial {
+        matches: Vec<SearchMatch>,
+    },
+    FilterResults {
+        lines: Vec<FilteredLine>,
+        total_lines: u64,
+        filter_complete: bool,
+        // Lines kept by the filter, of which `lines` is the page starting at `offset`
+        #[serde(default)]
+        total_matches: u64,
+        #[serde(default)]
+        offset: u64,
     },
-    // FilterResults {
-    //     matches: Vec<LogMatch>,
-    //     progress: f32,
-    // },
     Progress {
         percent: f32,
         message: String,
     },
+    SearchProgress {
+        percent: u8,
+    },
     Error {
         message: String,
+
+        // Lets clients handle the error without parsing the message
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<ErrorCode>,
     },
     Info {
         message: String,
     },
     FileTruncated {
         line_count: u64,
+        #[serde(default)]
+        trailing_newline: bool,
+    },
+    NoChange {
+        line_count: u64,
     },
     LinesAdded {
         old_line_count: u64,
         new_line_count: u64,
         new_lines: Vec<Vec<String>>,
+        #[serde(default)]
+        trailing_newline: bool,
+    },
+    TailStopped {
+        total_lines: u64,
+
+        // Why the watcher stopped on its own, null when a command stopped it
+        #[serde(default)]
+        error: Option<String>,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How a search ran
+/// Parsed: each column was searched, offsets are into the parsed column
+/// Raw: the whole line was searched, matches are in column 0 with offsets into the line
+/// Joined: the join_columns were searched as one text, matches are in column 0 with offsets
+/// into that text
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SearchMode {
+    Parsed,
+    #[default]
+    Raw,
+    Joined,
+}
+
+/// What happens to bytes that aren't valid in the file's encoding
+/// Replace: they become U+FFFD
+/// Skip: they're dropped
+/// Strict: reading them fails with a DecodeError
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DecodePolicy {
+    #[default]
+    Replace,
+    Skip,
+    Strict,
+}
+
+/// Watcher events sent to the client, the file is indexed as it changes whatever the mode
+/// All: LinesAdded and FileTruncated
+/// TruncationOnly: only FileTruncated
+/// None: neither
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum NotifyMode {
+    #[default]
+    All,
+    TruncationOnly,
+    None,
+}
+
+/// Encoding of the responses written to stdout, commands are always JSON lines
+/// Json: one response per line
+/// MessagePack: each response is prefixed with its length in bytes (u32, big-endian)
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ProtocolFormat {
+    #[default]
+    #[serde(alias = "json")]
+    Json,
+    #[serde(alias = "msgpack")]
+    MessagePack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchMatch {
-    pub line_number: u32,
+    pub line_number: u64,
     pub column: u8,
-    pub start_index: u16,
-    pub end_index: u16,
+    pub start_index: u64,
+    pub end_index: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormatScore {
+    pub log_format: LogFormat,
+    // Fraction of the sampled lines the format matches, from 0 to 1
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ByteMatch {
+    pub line_number: u64,
+    // Offset of the first matched byte in the file
+    pub byte_offset: u64,
+    // Offset of the first matched byte from the start of its line
+    pub line_offset: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredLine {
+    pub line_number: u64,
+    pub columns: Vec<String>,
+    // Matching lines with the same value as this one, only from a distinct filter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrences: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtractedRow {
+    pub line_number: u64,
+    // One value per capture group, null when the group didn't take part in the match
+    pub values: Vec<Option<String>>,
+}
+
+/// Kind of a Response::Error, for programmatic handling
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    FileNotFound,
+    PermissionDenied,
+    InvalidPath,
+    UnsupportedEncoding,
+    InvalidRegex,
+    InvalidArgument,
+    OutOfBounds,
+    FileTooLarge,
+    NotOpen,
+    DecodeError,
+    Io,
+    Internal,
+}
+
+/// An error message with its code, becomes a Response::Error
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<CodedError> for Response {
+    fn from(error: CodedError) -> Self {
+        Response::Error {
+            message: error.message,
+            code: Some(error.code),
+        }
+    }
+}
+
+// Lets `?` pass a CodedError through functions that still return String errors
+impl From<CodedError> for String {
+    fn from(error: CodedError) -> Self {
+        error.message
+    }
 }